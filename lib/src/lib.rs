@@ -1,7 +1,9 @@
 //! # A SOCKS protocol implementation
 //!
 //! This library provides a fully asynchronous, modular implementation of the
-//! SOCKS5 protocol as described in [RFC 1928](https://www.rfc-editor.org/rfc/rfc1928).
+//! SOCKS5 protocol as described in [RFC 1928](https://www.rfc-editor.org/rfc/rfc1928),
+//! alongside with the legacy SOCKS4 protocol and its SOCKS4a extension.
 
 pub mod codec;
+pub mod socks4;
 pub mod socks5;
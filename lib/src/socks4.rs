@@ -0,0 +1,5 @@
+//! Things for protocol version 4 and its 4a extension.
+
+pub mod client;
+pub mod proto;
+pub mod server;
@@ -0,0 +1,128 @@
+#![expect(
+    async_fn_in_trait,
+    reason = "clients are used through their concrete types"
+)]
+
+use futures::{
+    AsyncRead,
+    AsyncWrite,
+};
+
+use crate::{
+    codec::{
+        Decoder as _,
+        Encoder as _,
+    },
+    socks4::proto::{
+        Address,
+        CommandType,
+        ConversionError,
+        Reply,
+        messages::{
+            Request,
+            Response,
+        },
+    },
+};
+
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+#[derive(Debug)]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "named after the SOCKS5 client's error"
+)]
+pub enum ClientError {
+    IoError(std::io::Error),
+    Protocol(ConversionError),
+    /// The target address can't be expressed in the protocol, which is the
+    /// case for IPv6 addresses.
+    AddressNotSupported(crate::socks5::proto::Address),
+    RequestFailed(Reply),
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(value: std::io::Error) -> Self {
+        ClientError::IoError(value)
+    }
+}
+
+impl From<ConversionError> for ClientError {
+    fn from(value: ConversionError) -> Self {
+        ClientError::Protocol(value)
+    }
+}
+
+/// A generic trait for a SOCKS4 client.
+pub trait Client<T, S: AsyncRead + AsyncWrite + Unpin>: Sized {
+    /// Returns a mutable reference to the underlying I/O stream.
+    fn stream(&mut self) -> &mut S;
+
+    /// Establishes a connection to a target.
+    ///
+    /// Domain names are sent with the SOCKS4a extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::AddressNotSupported` for IPv6 targets, or another
+    /// `ClientError` if the connect request is rejected.
+    async fn connect_to_target(
+        self,
+        target_addr: crate::socks5::proto::Address,
+        target_port: u16,
+    ) -> Result<T, ClientError>;
+
+    /// Sends a request with the given command and reads the server's reply.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the server rejects the request or an I/O
+    /// error occurs.
+    async fn send_request(
+        &mut self,
+        command: CommandType,
+        target_addr: Address,
+        target_port: u16,
+        user_id: Box<[u8]>,
+    ) -> Result<Response, ClientError> {
+        let mut stream = self.stream();
+
+        let request = Request {
+            command,
+            address: target_addr,
+            port: target_port,
+            user_id,
+        };
+        request.write_to(&mut stream).await?;
+
+        let response = Response::read_from(&mut stream).await?;
+        if response.reply.is_success() {
+            Ok(response)
+        } else {
+            Err(ClientError::RequestFailed(response.reply))
+        }
+    }
+
+    /// Sends a `CONNECT` request to the SOCKS4 server to establish a proxy
+    /// connection.
+    ///
+    /// On success, the stream is ready to relay data to and from the target.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::AddressNotSupported` for IPv6 targets, or another
+    /// `ClientError` if the server rejects the request or an I/O error occurs.
+    async fn send_connect_request(
+        &mut self,
+        target_addr: crate::socks5::proto::Address,
+        target_port: u16,
+        user_id: Box<[u8]>,
+    ) -> Result<(), ClientError> {
+        let target_addr =
+            Address::try_from(target_addr).map_err(ClientError::AddressNotSupported)?;
+        self.send_request(CommandType::CONNECT, target_addr, target_port, user_id)
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,49 @@
+use tokio::net::TcpStream;
+use tokio_util::compat::{
+    Compat,
+    TokioAsyncReadCompatExt as _,
+};
+
+use crate::socks4::client::{
+    Client,
+    ClientError,
+};
+
+/// A Tokio-based SOCKS4 client, speaking the SOCKS4a extension for domain
+/// names.
+pub struct Socks4Client {
+    stream:  Compat<TcpStream>,
+    user_id: Box<[u8]>,
+}
+
+impl Socks4Client {
+    pub fn new(stream: TcpStream, user_id: Box<[u8]>) -> Self {
+        Self {
+            stream: stream.compat(),
+            user_id,
+        }
+    }
+}
+
+impl Client<TcpStream, Compat<TcpStream>> for Socks4Client {
+    fn stream(&mut self) -> &mut Compat<TcpStream> {
+        &mut self.stream
+    }
+
+    /// Establishes a connection to a target host via the SOCKS4 proxy.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::AddressNotSupported` for IPv6 targets. Other
+    /// errors can occur if the connection request fails.
+    async fn connect_to_target(
+        mut self,
+        target_addr: crate::socks5::proto::Address,
+        target_port: u16,
+    ) -> Result<TcpStream, ClientError> {
+        let user_id = self.user_id.clone();
+        self.send_connect_request(target_addr, target_port, user_id)
+            .await?;
+        Ok(self.stream.into_inner())
+    }
+}
@@ -0,0 +1,97 @@
+pub mod messages;
+
+use caret::caret_int;
+
+pub use crate::socks5::proto::ConversionError;
+
+pub const VERSION: u8 = 0x04;
+
+/// The version byte of the server's reply, which is not the protocol version.
+pub const REPLY_VERSION: u8 = 0x00;
+
+/// The maximum length of the null-terminated `USERID` and hostname fields.
+pub const MAX_FIELD_LEN: usize = 255;
+
+caret_int! {
+    pub struct CommandType(u8) {
+        CONNECT = 0x01,
+        BIND = 0x02,
+    }
+}
+
+caret_int! {
+    pub struct Reply(u8) {
+        GRANTED = 0x5A,
+        REJECTED = 0x5B,
+        IDENTD_UNREACHABLE = 0x5C,
+        IDENTD_MISMATCH = 0x5D,
+    }
+}
+
+impl Reply {
+    pub fn is_success(&self) -> bool {
+        self == &Reply::GRANTED
+    }
+}
+
+/// The destination of a request.
+///
+/// Domain names are only supported by the 4a extension, which encodes them
+/// after the `USERID` field and marks the `DSTIP` field with an invalid
+/// address of the form `0.0.0.x`, where `x` is non-zero.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Address {
+    Ipv4(std::net::Ipv4Addr),
+    Domain(Box<[u8]>),
+}
+
+impl Address {
+    /// The `DSTIP` value announcing a 4a domain name.
+    pub const DOMAIN_MARKER: std::net::Ipv4Addr = std::net::Ipv4Addr::new(0, 0, 0, 1);
+
+    /// Returns `true` if the `DSTIP` value announces a 4a domain name.
+    pub const fn is_domain_marker(addr: &std::net::Ipv4Addr) -> bool {
+        matches!(addr.octets(), [0, 0, 0, 1 ..= 0xFF])
+    }
+}
+
+impl From<std::net::Ipv4Addr> for Address {
+    fn from(value: std::net::Ipv4Addr) -> Self {
+        Address::Ipv4(value)
+    }
+}
+
+impl From<&str> for Address {
+    fn from(value: &str) -> Self {
+        Address::Domain(value.as_bytes().into())
+    }
+}
+
+impl From<Box<[u8]>> for Address {
+    fn from(value: Box<[u8]>) -> Self {
+        Address::Domain(value)
+    }
+}
+
+impl TryFrom<crate::socks5::proto::Address> for Address {
+    type Error = crate::socks5::proto::Address;
+
+    /// Fails for [crate::socks5::proto::Address::Ipv6], which can't be
+    /// expressed in any of the protocol's flavours, returning it back.
+    fn try_from(value: crate::socks5::proto::Address) -> Result<Self, Self::Error> {
+        match value {
+            | crate::socks5::proto::Address::Ipv4(addr) => Ok(Address::Ipv4(addr)),
+            | crate::socks5::proto::Address::Domain(domain) => Ok(Address::Domain(domain)),
+            | crate::socks5::proto::Address::Ipv6(_) => Err(value),
+        }
+    }
+}
+
+impl From<Address> for crate::socks5::proto::Address {
+    fn from(value: Address) -> Self {
+        match value {
+            | Address::Ipv4(addr) => crate::socks5::proto::Address::Ipv4(addr),
+            | Address::Domain(domain) => crate::socks5::proto::Address::Domain(domain),
+        }
+    }
+}
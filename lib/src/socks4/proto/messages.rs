@@ -0,0 +1,183 @@
+use futures::{
+    AsyncRead,
+    AsyncReadExt as _,
+    AsyncWrite,
+    AsyncWriteExt as _,
+};
+
+use super::*;
+use crate::codec::{
+    Decoder,
+    Encoder,
+};
+
+/// Reads a null-terminated field of at most [MAX_FIELD_LEN] bytes.
+///
+/// # Errors
+///
+/// Returns [ConversionError::MalformedMessage] if the field is too long.
+async fn read_null_terminated<R: AsyncRead + Unpin>(
+    reader: &mut R,
+) -> Result<Box<[u8]>, ConversionError> {
+    let mut field = Vec::new();
+    loop {
+        let mut byte_buf = [0_u8; 1];
+        reader.read_exact(&mut byte_buf).await?;
+        if byte_buf[0] == 0x00 {
+            return Ok(field.into_boxed_slice());
+        }
+        if field.len() >= MAX_FIELD_LEN {
+            return Err(ConversionError::MalformedMessage);
+        }
+        field.push(byte_buf[0]);
+    }
+}
+
+/// Appends a null-terminated field to the message.
+///
+/// # Errors
+///
+/// Returns [ConversionError::MalformedMessage] if the field is longer than
+/// [MAX_FIELD_LEN] bytes or holds a null byte.
+fn push_null_terminated(message: &mut Vec<u8>, field: &[u8]) -> Result<(), ConversionError> {
+    if field.len() > MAX_FIELD_LEN || field.contains(&0x00) {
+        return Err(ConversionError::MalformedMessage);
+    }
+    message.extend_from_slice(field);
+    message.push(0x00);
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub command: CommandType,
+    pub address: Address,
+    pub port:    u16,
+    pub user_id: Box<[u8]>,
+}
+
+impl Encoder<ConversionError> for Request {
+    /// # Errors
+    ///
+    /// Returns [ConversionError::MalformedMessage] if the address is of the
+    /// form `0.0.0.x` with a non-zero `x`, which would announce a domain name,
+    /// or if a field is too long or holds a null byte. Nothing is written then.
+    async fn write_to<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<(), ConversionError> {
+        let mut message = vec![VERSION, self.command.0];
+
+        message.extend_from_slice(&self.port.to_be_bytes());
+
+        match &self.address {
+            | Address::Ipv4(addr) => {
+                if Address::is_domain_marker(addr) {
+                    return Err(ConversionError::MalformedMessage);
+                }
+                message.extend_from_slice(&addr.octets());
+                push_null_terminated(&mut message, &self.user_id)?;
+            },
+            | Address::Domain(domain) => {
+                message.extend_from_slice(&Address::DOMAIN_MARKER.octets());
+                push_null_terminated(&mut message, &self.user_id)?;
+                push_null_terminated(&mut message, domain)?;
+            },
+        }
+
+        writer.write_all(&message).await?;
+
+        Ok(())
+    }
+}
+
+impl Decoder<ConversionError> for Request {
+    async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self, ConversionError> {
+        let mut ver_buf = [0_u8; 1];
+        reader.read_exact(&mut ver_buf).await?;
+        if ver_buf[0] != VERSION {
+            return Err(ConversionError::InvalidProtocolVersion(ver_buf[0]));
+        }
+
+        let mut cmd_buf = [0_u8; 1];
+        reader.read_exact(&mut cmd_buf).await?;
+        let command = CommandType(cmd_buf[0]);
+
+        let mut port_buf = [0_u8; 2];
+        reader.read_exact(&mut port_buf).await?;
+        let port = u16::from_be_bytes(port_buf);
+
+        let mut octets = [0_u8; 4];
+        reader.read_exact(&mut octets).await?;
+        let dst_ip = std::net::Ipv4Addr::from(octets);
+
+        let user_id = read_null_terminated(reader).await?;
+
+        let address = if Address::is_domain_marker(&dst_ip) {
+            Address::Domain(read_null_terminated(reader).await?)
+        } else {
+            Address::Ipv4(dst_ip)
+        };
+
+        Ok(Request {
+            command,
+            address,
+            port,
+            user_id,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub reply:   Reply,
+    pub address: std::net::Ipv4Addr,
+    pub port:    u16,
+}
+
+impl Response {
+    pub const REJECTED: Response = Self::new_error(Reply::REJECTED);
+
+    const fn new_error(reply: Reply) -> Self {
+        Self {
+            reply,
+            address: std::net::Ipv4Addr::UNSPECIFIED,
+            port: 0,
+        }
+    }
+}
+
+impl Encoder<ConversionError> for Response {
+    async fn write_to<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<(), ConversionError> {
+        writer.write_all(&[REPLY_VERSION]).await?;
+
+        writer.write_all(&[self.reply.0]).await?;
+
+        writer.write_all(&self.port.to_be_bytes()).await?;
+        writer.write_all(&self.address.octets()).await?;
+
+        Ok(())
+    }
+}
+
+impl Decoder<ConversionError> for Response {
+    async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self, ConversionError> {
+        let mut header = [0_u8; 2];
+        reader.read_exact(&mut header).await?;
+        if header[0] != REPLY_VERSION {
+            return Err(ConversionError::InvalidProtocolVersion(header[0]));
+        }
+        let reply = Reply(header[1]);
+
+        let mut port_buf = [0_u8; 2];
+        reader.read_exact(&mut port_buf).await?;
+        let port = u16::from_be_bytes(port_buf);
+
+        let mut octets = [0_u8; 4];
+        reader.read_exact(&mut octets).await?;
+        let address = std::net::Ipv4Addr::from(octets);
+
+        Ok(Response {
+            reply,
+            address,
+            port,
+        })
+    }
+}
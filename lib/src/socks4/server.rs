@@ -0,0 +1,167 @@
+#![expect(
+    async_fn_in_trait,
+    reason = "servers are used through their concrete types"
+)]
+
+use futures::{
+    AsyncRead,
+    AsyncWrite,
+};
+
+use crate::{
+    codec::{
+        Decoder as _,
+        Encoder as _,
+    },
+    socks4::proto::{
+        CommandType,
+        ConversionError,
+        Reply,
+        messages::{
+            Request,
+            Response,
+        },
+    },
+};
+
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+#[derive(Debug)]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "named after the SOCKS5 server's error"
+)]
+pub enum ServerError {
+    /// An I/O error occured while communicating with the client.
+    IoError(std::io::Error),
+
+    /// A protocol-level error occurred (e.g., client sent a malformed message).
+    Protocol(ConversionError),
+
+    /// The client requested a command that is not supported by the server.
+    CommandNotSupported(CommandType),
+
+    /// The server failed to fulfill the client's request.
+    RequestFailed(Reply),
+}
+
+impl From<std::io::Error> for ServerError {
+    fn from(value: std::io::Error) -> Self {
+        ServerError::IoError(value)
+    }
+}
+
+impl From<ConversionError> for ServerError {
+    fn from(value: ConversionError) -> Self {
+        ServerError::Protocol(value)
+    }
+}
+
+/// A generic trait for a SOCKS4 server.
+///
+/// The protocol has no handshake: the client's request is the first message,
+/// and its `USERID` field is the only identification available.
+pub trait Server<S: AsyncRead + AsyncWrite + Unpin, T = ()>: Sized {
+    /// Returns a mutable reference to the underlying I/O stream.
+    fn stream(&mut self) -> &mut S;
+
+    /// Processes a single client connection through its entire lifecycle.
+    #[inline]
+    async fn serve_client(self) -> Result<T, ServerError> {
+        self.handle_request().await
+    }
+
+    /// Handles the client's request.
+    ///
+    /// This method reads the client's command request and dispatches it to the
+    /// appropriate handler (e.g., `handle_connect`).
+    async fn handle_request(self) -> Result<T, ServerError> {
+        default_handle_request_impl(self).await
+    }
+
+    /// Handles the [CommandType::CONNECT] request from the client.
+    ///
+    /// This method is responsible for connecting to the target address
+    /// specified in the request and then relaying data between the client
+    /// and the target.
+    async fn handle_connect(self, request: Request) -> Result<T, ServerError>;
+
+    /// Handles the [CommandType::BIND] request from the client.
+    ///
+    /// ### Note
+    ///
+    /// The default implementation rejects the request with
+    /// [Reply::REJECTED].
+    async fn handle_bind(mut self, request: Request) -> Result<T, ServerError> {
+        reject_command_impl(self.stream(), request.command).await
+    }
+}
+
+/// The default implementation for the [Server::handle_request] method.
+///
+/// Reads the request and dispatches it.
+///
+/// # Errors
+///
+/// Returns a [ServerError] if the request can't be read, the command is
+/// unknown, or the handler fails.
+#[inline]
+pub async fn default_handle_request_impl<E: Server<S, T>, S: AsyncRead + AsyncWrite + Unpin, T>(
+    mut server: E,
+) -> Result<T, ServerError> {
+    let request = Request::read_from(server.stream()).await?;
+    dispatch_request_impl(server, request).await
+}
+
+/// Dispatches a request to the handler of its command.
+///
+/// Only supports the [CommandType::CONNECT] and [CommandType::BIND] commands.
+///
+/// # Errors
+///
+/// Returns a [ServerError] if the command is unknown or the handler fails.
+#[inline]
+pub async fn dispatch_request_impl<E: Server<S, T>, S: AsyncRead + AsyncWrite + Unpin, T>(
+    mut server: E,
+    request: Request,
+) -> Result<T, ServerError> {
+    match request.command {
+        | CommandType::CONNECT => server.handle_connect(request).await,
+        | CommandType::BIND => server.handle_bind(request).await,
+        | _ => reject_command_impl(server.stream(), request.command).await,
+    }
+}
+
+/// Reads the request and replies with [Response::REJECTED], e.g. for clients
+/// that are not allowed to use the server.
+///
+/// # Errors
+///
+/// Always returns an error: either the error from reading the request or
+/// writing the reply, or [ServerError::RequestFailed].
+#[inline]
+pub async fn reject_request_impl<S: AsyncRead + AsyncWrite + Unpin, T>(
+    stream: &mut S,
+) -> Result<T, ServerError> {
+    Request::read_from(stream).await?;
+    Response::REJECTED.write_to(stream).await?;
+    Err(ServerError::RequestFailed(Reply::REJECTED))
+}
+
+/// Replies with [Response::REJECTED] and fails with
+/// [ServerError::CommandNotSupported].
+///
+/// # Errors
+///
+/// Always returns an error: either the I/O error from writing the reply or
+/// [ServerError::CommandNotSupported].
+#[inline]
+pub async fn reject_command_impl<S: AsyncWrite + Unpin, T>(
+    stream: &mut S,
+    command: CommandType,
+) -> Result<T, ServerError> {
+    let response = Response::REJECTED;
+    response.write_to(stream).await?;
+    Err(ServerError::CommandNotSupported(command))
+}
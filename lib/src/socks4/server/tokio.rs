@@ -0,0 +1,174 @@
+use std::net::{
+    IpAddr,
+    Ipv4Addr,
+    SocketAddr,
+};
+
+use tokio::net::{
+    TcpListener,
+    TcpStream,
+};
+use tokio_util::compat::{
+    Compat,
+    TokioAsyncReadCompatExt as _,
+};
+
+use crate::{
+    codec::Encoder as _,
+    socks4::{
+        proto::{
+            Address,
+            Reply,
+            messages::{
+                Request,
+                Response,
+            },
+        },
+        server::{
+            Server,
+            ServerError,
+        },
+    },
+    socks5::server::tokio::relay,
+};
+
+/// A Tokio-based SOCKS4 server for handling a single client connection.
+///
+/// The [Server] implementation for this struct provides these capabilities:
+/// * SOCKS4a domain names;
+/// * [CommandType::CONNECT](crate::socks4::proto::CommandType::CONNECT) command
+///   support;
+/// * [CommandType::BIND](crate::socks4::proto::CommandType::BIND) command
+///   support.
+///
+/// The `USERID` field of requests is not checked, see
+/// [Socks5Listener::with_socks4](crate::socks5::server::tokio::Socks5Listener::with_socks4)
+/// for a server deciding who may use the protocol.
+pub struct Socks4Server {
+    stream: Compat<TcpStream>,
+}
+
+impl Socks4Server {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream: stream.compat(),
+        }
+    }
+}
+
+impl Server<Compat<TcpStream>> for Socks4Server {
+    #[inline]
+    fn stream(&mut self) -> &mut Compat<TcpStream> {
+        &mut self.stream
+    }
+
+    /// Handles a [CommandType::CONNECT](crate::socks4::proto::CommandType::CONNECT) request from a SOCKS client.
+    ///
+    /// # Errors
+    /// Returns a [ServerError] if it fails to connect to the target or if there
+    /// are I/O errors during communication.
+    async fn handle_connect(mut self, request: Request) -> Result<(), ServerError> {
+        let port = request.port;
+
+        let target_stream = match request.address {
+            | Address::Ipv4(addr) => TcpStream::connect((addr, port)).await,
+            | Address::Domain(domain) => {
+                match std::str::from_utf8(&domain) {
+                    | Ok(domain) => TcpStream::connect((domain, port)).await,
+                    | Err(error) => {
+                        Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, error))
+                    },
+                }
+            },
+        };
+
+        let target_stream = match target_stream {
+            | Ok(stream) => stream,
+            | Err(_) => {
+                let response = Response::REJECTED;
+                response.write_to(self.stream()).await?;
+                return Err(ServerError::RequestFailed(Reply::REJECTED));
+            },
+        };
+
+        let response = Response {
+            reply:   Reply::GRANTED,
+            address: to_ipv4(target_stream.peer_addr()?),
+            port:    request.port,
+        };
+        response.write_to(self.stream()).await?;
+
+        relay(self.stream, target_stream).await;
+
+        Ok(())
+    }
+
+    /// Handles a [CommandType::BIND](crate::socks4::proto::CommandType::BIND)
+    /// request from a SOCKS client.
+    ///
+    /// The listening socket is opened on the same local address the client
+    /// connected to. If the request carries an IP address, only a peer with
+    /// that address is accepted.
+    ///
+    /// # Errors
+    /// Returns a [ServerError] if the listening socket cannot be opened, the
+    /// inbound peer is not the expected one or if there are I/O errors during
+    /// communication.
+    async fn handle_bind(mut self, request: Request) -> Result<(), ServerError> {
+        let local_ip = self.stream.get_ref().local_addr()?.ip();
+
+        let listener = match TcpListener::bind((local_ip, 0)).await {
+            | Ok(bound) => bound,
+            | Err(_) => {
+                Response::REJECTED.write_to(self.stream()).await?;
+                return Err(ServerError::RequestFailed(Reply::REJECTED));
+            },
+        };
+
+        let bound_addr = listener.local_addr()?;
+        let first_response = Response {
+            reply:   Reply::GRANTED,
+            address: to_ipv4(bound_addr),
+            port:    bound_addr.port(),
+        };
+        first_response.write_to(self.stream()).await?;
+
+        let (peer_stream, peer_addr) = match listener.accept().await {
+            | Ok(accepted) => accepted,
+            | Err(_) => {
+                Response::REJECTED.write_to(self.stream()).await?;
+                return Err(ServerError::RequestFailed(Reply::REJECTED));
+            },
+        };
+        drop(listener);
+
+        let is_expected = match request.address {
+            | Address::Ipv4(expected) => IpAddr::V4(expected) == peer_addr.ip(),
+            | Address::Domain(_) => true,
+        };
+        if !is_expected {
+            Response::REJECTED.write_to(self.stream()).await?;
+            return Err(ServerError::RequestFailed(Reply::REJECTED));
+        }
+
+        let second_response = Response {
+            reply:   Reply::GRANTED,
+            address: to_ipv4(peer_addr),
+            port:    peer_addr.port(),
+        };
+        second_response.write_to(self.stream()).await?;
+
+        relay(self.stream, peer_stream).await;
+
+        Ok(())
+    }
+}
+
+/// Returns the IPv4 address of the socket, or the unspecified address which
+/// clients are expected to replace with the server's one.
+fn to_ipv4(addr: SocketAddr) -> Ipv4Addr {
+    match addr.ip() {
+        | IpAddr::V4(ipv4) => ipv4,
+        | IpAddr::V6(ipv6) => ipv6.to_ipv4_mapped().unwrap_or(Ipv4Addr::UNSPECIFIED),
+    }
+}
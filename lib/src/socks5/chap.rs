@@ -0,0 +1,58 @@
+//! The cryptography of the
+//! [AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE](crate::socks5::proto::AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE)
+//! method.
+//!
+//! The client proves the knowledge of the password by returning the HMAC of
+//! the server's challenge keyed with the password, so the password itself is
+//! never sent.
+
+use hmac::{
+    Hmac,
+    digest::KeyInit,
+};
+use sha2::Sha256;
+use subtle::ConstantTimeEq as _;
+
+use crate::socks5::proto::messages::auth::chap::Algorithm;
+
+/// The algorithms used unless others are set, i.e. the ones of the draft.
+pub const DEFAULT_ALGORITHMS: [Algorithm; 1] = [Algorithm::HMAC_MD5];
+
+/// The length of the challenges issued by the server.
+pub const CHALLENGE_LEN: usize = 32;
+
+/// Returns a fresh random challenge.
+///
+/// # Errors
+///
+/// Returns an error if the system's random number generator fails.
+pub fn challenge() -> std::io::Result<[u8; CHALLENGE_LEN]> {
+    let mut challenge = [0_u8; CHALLENGE_LEN];
+    getrandom::fill(&mut challenge)?;
+    Ok(challenge)
+}
+
+/// Computes the response to the challenge, or returns `None` if the
+/// algorithm is not supported.
+pub fn response(algorithm: Algorithm, secret: &[u8], challenge: &[u8]) -> Option<Box<[u8]>> {
+    match algorithm {
+        | Algorithm::HMAC_MD5 => Some(hmac::<Hmac<md5::Md5>>(secret, challenge)),
+        | Algorithm::NON_STANDARD_HMAC_SHA256 => Some(hmac::<Hmac<Sha256>>(secret, challenge)),
+        | _ => None,
+    }
+}
+
+/// Checks the response to the challenge in constant time.
+pub fn verify(algorithm: Algorithm, secret: &[u8], challenge: &[u8], response: &[u8]) -> bool {
+    self::response(algorithm, secret, challenge)
+        .is_some_and(|expected| expected.ct_eq(response).into())
+}
+
+fn hmac<M: hmac::Mac + KeyInit>(secret: &[u8], message: &[u8]) -> Box<[u8]> {
+    // HMAC accepts keys of any length.
+    let Ok(mut code) = <M as hmac::Mac>::new_from_slice(secret) else {
+        return Box::default();
+    };
+    code.update(message);
+    code.finalize().into_bytes().as_slice().into()
+}
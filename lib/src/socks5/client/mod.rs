@@ -1,5 +1,7 @@
 #![allow(async_fn_in_trait)]
 
+use std::net::IpAddr;
+
 use futures::{
     AsyncRead,
     AsyncWrite,
@@ -10,18 +12,41 @@ use crate::{
         Decoder,
         Encoder,
     },
-    socks5::proto::{
-        Address,
-        AuthenticationMethod,
-        CommandType,
-        ConversionError,
-        Reply,
-        messages::{
-            ClientGreeting,
-            Request,
-            Response,
-            ServerChoice,
-            auth::username_password,
+    socks5::{
+        chap::response,
+        gssapi::{
+            Mechanism,
+            MechanismError,
+            Session,
+        },
+        proto::{
+            Address,
+            AuthenticationMethod,
+            CommandType,
+            ConversionError,
+            Reply,
+            Status,
+            messages::{
+                ClientGreeting,
+                Request,
+                Response,
+                ServerChoice,
+                auth::{
+                    chap::{
+                        self,
+                        Algorithm,
+                        Attribute,
+                        AttributeType,
+                    },
+                    gssapi::{
+                        Message,
+                        MessageType,
+                        ProtectionLevel,
+                    },
+                    multi_authentication::StageStatus,
+                    username_password,
+                },
+            },
         },
     },
 };
@@ -50,6 +75,12 @@ impl From<ConversionError> for ClientError {
     }
 }
 
+impl From<MechanismError> for ClientError {
+    fn from(_: MechanismError) -> Self {
+        ClientError::AuthenticationFailed
+    }
+}
+
 /// A generic trait for a SOCKS5 client.
 pub trait Client<T, S: AsyncRead + AsyncWrite + Unpin>: Sized {
     /// Returns a mutable reference to the underlying I/O stream.
@@ -91,26 +122,84 @@ pub trait Client<T, S: AsyncRead + AsyncWrite + Unpin>: Sized {
         Ok(choice.chosen_authentication_method)
     }
 
-    /// Sends a `CONNECT` request to the SOCKS5 server to establish a proxy
-    /// connection.
+    /// Establishes a [CommandType::BIND] on the proxy.
     ///
-    /// On success, the stream is ready to relay data to and from the target.
+    /// On success, the returned [BindHandle] carries the address the proxy
+    /// listens on and can be awaited for the inbound peer.
+    ///
+    /// ### Note
+    ///
+    /// The default implementation only supports
+    /// [AuthenticationMethod::NO_AUTHENTICATION], and is only available to
+    /// clients which can be turned into their stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the handshake fails, the server requires
+    /// authentication, or the bind request is rejected.
+    async fn bind_to_target(
+        mut self,
+        target_addr: Address,
+        target_port: u16,
+    ) -> Result<BindHandle<S>, ClientError>
+    where
+        Self: Into<S>,
+    {
+        negotiate_without_authentication(&mut self).await?;
+        let (address, port) = self.send_bind_request(target_addr, target_port).await?;
+        Ok(BindHandle::new(self.into(), address, port))
+    }
+
+    /// Establishes a [CommandType::UDP_ASSOCIATE] on the proxy.
+    ///
+    /// The address and port are the ones the client expects to send datagrams
+    /// from, and may be left unspecified. On success, the returned
+    /// [UdpAssociation] carries the address of the proxy's UDP relay and the
+    /// stream that keeps the association alive.
+    ///
+    /// ### Note
+    ///
+    /// The default implementation only supports
+    /// [AuthenticationMethod::NO_AUTHENTICATION], and is only available to
+    /// clients which can be turned into their stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the handshake fails, the server requires
+    /// authentication, or the request is rejected.
+    async fn associate_udp(
+        mut self,
+        source_addr: Address,
+        source_port: u16,
+    ) -> Result<UdpAssociation<S>, ClientError>
+    where
+        Self: Into<S>,
+    {
+        negotiate_without_authentication(&mut self).await?;
+        let (address, port) = self
+            .send_udp_associate_request(source_addr, source_port)
+            .await?;
+        Ok(UdpAssociation::new(self.into(), address, port))
+    }
+
+    /// Sends a request with the given command and reads the server's reply.
     ///
     /// # Errors
     ///
     /// Returns a `ClientError` if the server rejects the request or an I/O
     /// error occurs.
-    async fn send_connect_request(
+    async fn send_request(
         &mut self,
+        command: CommandType,
         target_addr: Address,
         target_port: u16,
-    ) -> Result<(), ClientError> {
+    ) -> Result<Response, ClientError> {
         let mut stream = self.stream();
 
         let request = Request {
-            command: CommandType::CONNECT,
+            command,
             address: target_addr,
-            port:    target_port,
+            port: target_port,
         };
         request.write_to(&mut stream).await?;
 
@@ -118,9 +207,203 @@ pub trait Client<T, S: AsyncRead + AsyncWrite + Unpin>: Sized {
         if response.reply != Reply::SUCCESS {
             Err(ClientError::RequestFailed(response.reply))
         } else {
-            Ok(())
+            Ok(response)
         }
     }
+
+    /// Sends a `CONNECT` request to the SOCKS5 server to establish a proxy
+    /// connection.
+    ///
+    /// On success, the stream is ready to relay data to and from the target.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the server rejects the request or an I/O
+    /// error occurs.
+    async fn send_connect_request(
+        &mut self,
+        target_addr: Address,
+        target_port: u16,
+    ) -> Result<(), ClientError> {
+        self.send_request(CommandType::CONNECT, target_addr, target_port)
+            .await?;
+        Ok(())
+    }
+
+    /// Sends a `BIND` request to the SOCKS5 server.
+    ///
+    /// On success, returns the address and port the server listens on for
+    /// the inbound connection, as reported in the first reply.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the server rejects the request or an I/O
+    /// error occurs.
+    async fn send_bind_request(
+        &mut self,
+        target_addr: Address,
+        target_port: u16,
+    ) -> Result<(Address, u16), ClientError> {
+        let response = self
+            .send_request(CommandType::BIND, target_addr, target_port)
+            .await?;
+        Ok((response.address, response.port))
+    }
+
+    /// Sends a `UDP ASSOCIATE` request to the SOCKS5 server.
+    ///
+    /// The address and port are the ones the client expects to send datagrams
+    /// from, and may be left unspecified. On success, returns the address and
+    /// port of the server's UDP relay. The association lasts as long as the
+    /// stream stays open.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the server rejects the request or an I/O
+    /// error occurs.
+    async fn send_udp_associate_request(
+        &mut self,
+        source_addr: Address,
+        source_port: u16,
+    ) -> Result<(Address, u16), ClientError> {
+        let response = self
+            .send_request(CommandType::UDP_ASSOCIATE, source_addr, source_port)
+            .await?;
+        Ok((response.address, response.port))
+    }
+
+    /// Sends a Tor `RESOLVE` request to the SOCKS5 server.
+    ///
+    /// On success, returns the address the domain name resolved to.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the server rejects the request or an I/O
+    /// error occurs.
+    async fn send_resolve_request(&mut self, domain: Address) -> Result<Address, ClientError> {
+        let response = self.send_request(CommandType::RESOLVE, domain, 0).await?;
+        Ok(response.address)
+    }
+
+    /// Sends a Tor `RESOLVE_PTR` request to the SOCKS5 server.
+    ///
+    /// On success, returns the domain name the address resolved to.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the server rejects the request or an I/O
+    /// error occurs.
+    async fn send_resolve_ptr_request(&mut self, address: IpAddr) -> Result<Address, ClientError> {
+        let response = self
+            .send_request(CommandType::RESOLVE_PTR, address.into(), 0)
+            .await?;
+        Ok(response.address)
+    }
+}
+
+/// Performs the handshake offering [AuthenticationMethod::NO_AUTHENTICATION]
+/// alone.
+///
+/// # Errors
+///
+/// Returns `ClientError::UnsupportedAuthMethod` if the server requires
+/// authentication, or another `ClientError` if the handshake fails.
+async fn negotiate_without_authentication<L: Client<T, S>, T, S: AsyncRead + AsyncWrite + Unpin>(
+    client: &mut L,
+) -> Result<(), ClientError> {
+    let choice = client
+        .perform_handshake([AuthenticationMethod::NO_AUTHENTICATION].into())
+        .await?;
+    if choice == AuthenticationMethod::NO_AUTHENTICATION {
+        Ok(())
+    } else {
+        Err(ClientError::UnsupportedAuthMethod(choice))
+    }
+}
+
+/// A [CommandType::BIND] request that got its first reply.
+///
+/// The proxy is listening on [BindHandle::address] and [BindHandle::port]
+/// until a peer connects, which is reported by the second reply.
+#[derive(Debug)]
+pub struct BindHandle<S> {
+    stream:  S,
+    address: Address,
+    port:    u16,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> BindHandle<S> {
+    pub const fn new(stream: S, address: Address, port: u16) -> Self {
+        Self {
+            stream,
+            address,
+            port,
+        }
+    }
+
+    /// The address the proxy listens on.
+    pub const fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// The port the proxy listens on.
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Waits for the second reply of the exchange.
+    ///
+    /// On success, returns the stream that is ready to relay data to and from
+    /// the peer alongside with the peer's address and port.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the server reports a failure or an I/O
+    /// error occurs.
+    pub async fn accept(mut self) -> Result<(S, Address, u16), ClientError> {
+        let response = Response::read_from(&mut self.stream).await?;
+        if response.reply.is_success() {
+            Ok((self.stream, response.address, response.port))
+        } else {
+            Err(ClientError::RequestFailed(response.reply))
+        }
+    }
+}
+
+/// A [CommandType::UDP_ASSOCIATE] request that got its reply.
+///
+/// The proxy relays datagrams sent to [UdpAssociation::address] and
+/// [UdpAssociation::port] for as long as the stream stays open.
+#[derive(Debug)]
+pub struct UdpAssociation<S> {
+    stream:  S,
+    address: Address,
+    port:    u16,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> UdpAssociation<S> {
+    pub const fn new(stream: S, address: Address, port: u16) -> Self {
+        Self {
+            stream,
+            address,
+            port,
+        }
+    }
+
+    /// The address of the proxy's UDP relay.
+    pub const fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// The port of the proxy's UDP relay.
+    pub const fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the stream that keeps the association alive.
+    pub fn into_stream(self) -> S {
+        self.stream
+    }
 }
 
 /// The implementation of the [AuthenticationMethod::USERNAME_PASSWORD]
@@ -145,3 +428,179 @@ pub async fn username_password_auth_impl<L: Client<T, S>, T, S: AsyncRead + Asyn
         Ok(())
     }
 }
+
+/// The implementation of the [AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE]
+/// exchange.
+///
+/// The `algorithms` are offered in the order of preference, and the
+/// server's challenge is answered with its HMAC keyed with the password.
+///
+/// # Errors
+///
+/// Returns `ClientError::AuthenticationFailed` if the server rejects the
+/// response or picks an algorithm that was not offered.
+pub async fn chap_auth_impl<L: Client<T, S>, T, S: AsyncRead + AsyncWrite + Unpin>(
+    client: &mut L,
+    username: Box<[u8]>,
+    password: &[u8],
+    algorithms: &[Algorithm],
+) -> Result<(), ClientError> {
+    let mut stream = client.stream();
+
+    let offer: Box<[u8]> = algorithms
+        .iter()
+        .map(|algorithm| (*algorithm).into())
+        .collect();
+    chap::Message::new([Attribute::new(AttributeType::ALGORITHMS, offer)])
+        .write_to(&mut stream)
+        .await?;
+
+    let reply = chap::Message::read_from(&mut stream).await?;
+    if reply.get_status().is_some_and(|status| status.is_failure()) {
+        return Err(ClientError::AuthenticationFailed);
+    }
+    let algorithm = match reply.get(AttributeType::ALGORITHMS) {
+        | Some(&[algorithm]) => Algorithm::from(algorithm),
+        | _ => return Err(ClientError::AuthenticationFailed),
+    };
+    if !algorithms.contains(&algorithm) {
+        return Err(ClientError::AuthenticationFailed);
+    }
+    let response = reply
+        .get(AttributeType::CHALLENGE)
+        .and_then(|challenge| response(algorithm, password, challenge))
+        .ok_or(ClientError::AuthenticationFailed)?;
+
+    chap::Message::new([
+        Attribute::new(AttributeType::USER_IDENTITY, username),
+        Attribute::new(AttributeType::RESPONSE, response),
+    ])
+    .write_to(&mut stream)
+    .await?;
+
+    let status = chap::Message::read_from(&mut stream).await?.get_status();
+    if status == Some(Status::SUCCESS) {
+        Ok(())
+    } else {
+        Err(ClientError::AuthenticationFailed)
+    }
+}
+
+/// Reads the next [AuthenticationMethod::IANA_MULTI_AUTHENTICATION_FRAMEWORK]
+/// stage status.
+///
+/// It's called once the method is chosen, and then after every stage. Returns
+/// the method of the next stage, whose sub-negotiation is then up to the
+/// client, or `None` once all stages are passed.
+///
+/// # Errors
+///
+/// Returns `ClientError::AuthenticationFailed` if the server reports a
+/// failure.
+pub async fn read_stage_status_impl<L: Client<T, S>, T, S: AsyncRead + AsyncWrite + Unpin>(
+    client: &mut L,
+) -> Result<Option<AuthenticationMethod>, ClientError> {
+    let status = StageStatus::read_from(client.stream()).await?;
+    if status.status.is_failure() {
+        Err(ClientError::AuthenticationFailed)
+    } else {
+        Ok(status.next_stage())
+    }
+}
+
+/// The implementation of the [AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK]
+/// exchange.
+///
+/// # Errors
+///
+/// Returns `ClientError::AuthenticationFailed` if the server rejects the
+/// parameters.
+#[cfg(feature = "json")]
+pub async fn json_parameters_auth_impl<L: Client<T, S>, T, S: AsyncRead + AsyncWrite + Unpin>(
+    client: &mut L,
+    parameters: &serde_json::Map<String, serde_json::Value>,
+) -> Result<(), ClientError> {
+    use crate::socks5::proto::messages::auth::json_parameter_block;
+
+    let mut stream = client.stream();
+
+    let block = json_parameter_block::ParameterBlock {
+        parameters: serde_json::to_vec(parameters)
+            .map_err(std::io::Error::from)?
+            .into_boxed_slice(),
+    };
+    block.write_to(&mut stream).await?;
+
+    let response = json_parameter_block::ServerResponse::read_from(&mut stream).await?;
+    if response.status.is_failure() {
+        Err(ClientError::AuthenticationFailed)
+    } else {
+        Ok(())
+    }
+}
+
+/// The implementation of the [AuthenticationMethod::GSSAPI] exchange.
+///
+/// Establishes a security context with the mechanism for the `target`
+/// service and negotiates the protection level. The first of `levels` is
+/// requested, and any of them is accepted from the server. On success, the
+/// returned [Session] is meant to encapsulate the stream for the rest of the
+/// session.
+///
+/// # Errors
+///
+/// Returns `ClientError::AuthenticationFailed` if the context can't be
+/// established, the server aborts the exchange or picks a level that is not
+/// accepted.
+pub async fn gssapi_auth_impl<L: Client<T, S>, T, S: AsyncRead + AsyncWrite + Unpin>(
+    client: &mut L,
+    mechanism: &dyn Mechanism,
+    target: &str,
+    levels: &[ProtectionLevel],
+) -> Result<Session, ClientError> {
+    let stream = client.stream();
+
+    let requested = *levels.first().ok_or(ClientError::AuthenticationFailed)?;
+    let mut context = mechanism.initiate(target).map_err(ClientError::from)?;
+
+    let mut output = context.step(None).map_err(ClientError::from)?;
+    loop {
+        if let Some(token) = output {
+            Message::new(MessageType::AUTHENTICATION, token)
+                .write_to(stream)
+                .await?;
+        }
+        if context.is_established() {
+            break;
+        }
+
+        let message = Message::read_from(stream).await?;
+        if message.message_type != MessageType::AUTHENTICATION {
+            return Err(ClientError::AuthenticationFailed);
+        }
+        output = context
+            .step(Some(&message.token))
+            .map_err(ClientError::from)?;
+    }
+
+    let token = context
+        .wrap(&[requested.into()], false)
+        .map_err(ClientError::from)?;
+    Message::new(MessageType::PROTECTION_LEVEL, token)
+        .write_to(stream)
+        .await?;
+
+    let message = Message::read_from(stream).await?;
+    if message.message_type != MessageType::PROTECTION_LEVEL {
+        return Err(ClientError::AuthenticationFailed);
+    }
+    let level = match *context.unwrap(&message.token).map_err(ClientError::from)? {
+        | [level] => ProtectionLevel::from(level),
+        | _ => return Err(ClientError::AuthenticationFailed),
+    };
+    if !levels.contains(&level) {
+        return Err(ClientError::AuthenticationFailed);
+    }
+
+    Ok(Session::new(context, level))
+}
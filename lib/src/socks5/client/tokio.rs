@@ -1,42 +1,171 @@
-use std::sync::Arc;
+use std::{
+    net::{
+        IpAddr,
+        SocketAddr,
+    },
+    sync,
+};
 
-use tokio::net::TcpStream;
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncReadExt as _,
+        AsyncWrite,
+    },
+    net::{
+        TcpStream,
+        UdpSocket,
+        lookup_host,
+    },
+};
+#[cfg(feature = "tls")]
+use tokio_rustls::client::TlsStream;
 use tokio_util::compat::{
     Compat,
     TokioAsyncReadCompatExt,
 };
 
-use crate::socks5::{
-    client::{
-        Client,
-        ClientError,
-        username_password_auth_impl,
+#[cfg(feature = "json")]
+use crate::socks5::client::json_parameters_auth_impl;
+#[cfg(feature = "tls")]
+use crate::socks5::tls::{
+    Connector,
+    rustls::pki_types::ServerName,
+};
+use crate::{
+    codec::{
+        Decoder as _,
+        Encoder as _,
     },
-    proto::{
-        Address,
-        AuthenticationMethod,
+    socks5::{
+        chap::DEFAULT_ALGORITHMS,
+        client::{
+            BindHandle,
+            Client,
+            ClientError,
+            UdpAssociation,
+            chap_auth_impl,
+            gssapi_auth_impl,
+            read_stage_status_impl,
+            username_password_auth_impl,
+        },
+        gssapi::{
+            EncapsulatedStream,
+            Mechanism,
+        },
+        proto::{
+            Address,
+            AuthenticationMethod,
+            CommandType,
+            messages::{
+                Request,
+                Response,
+                UdpHeader,
+                auth::{
+                    chap::Algorithm,
+                    gssapi::ProtectionLevel,
+                },
+            },
+            reassembly::{
+                self,
+                Reassembler,
+            },
+        },
     },
 };
 
-type CredentialsHolder = Arc<(Box<[u8]>, Box<[u8]>)>;
+type CredentialsHolder = sync::Arc<(Box<[u8]>, Box<[u8]>)>;
 
 /// A Tokio-based SOCKS5 client.
-pub struct Socks5Client {
-    stream:      Compat<TcpStream>,
-    credentials: CredentialsHolder,
+///
+/// It talks to the proxy over a [TcpStream] by default, or over any other
+/// stream, e.g. a TLS one.
+pub struct Socks5Client<S = TcpStream> {
+    stream:               Compat<S>,
+    credentials:          CredentialsHolder,
+    algorithms:           Box<[Algorithm]>,
+    #[cfg(feature = "json")]
+    parameters:           Option<serde_json::Map<String, serde_json::Value>>,
+    multi_authentication: bool,
 }
 
 impl Socks5Client {
     pub fn new(stream: TcpStream, credentials: CredentialsHolder) -> Self {
+        Self::from_stream(stream, credentials)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Socks5Client<TlsStream<TcpStream>> {
+    /// Wraps the connection to the proxy in TLS, so that the whole session,
+    /// credentials included, is encrypted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the TLS handshake fails.
+    pub async fn connect_tls(
+        stream: TcpStream,
+        connector: &Connector,
+        server_name: ServerName<'static>,
+        credentials: CredentialsHolder,
+    ) -> std::io::Result<Self> {
+        let stream = connector.connect(server_name, stream).await?;
+        Ok(Self::from_stream(stream, credentials))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Socks5Client<S> {
+    /// Creates a client talking to the proxy over the given stream.
+    pub fn from_stream(stream: S, credentials: CredentialsHolder) -> Self {
         Self {
             stream: stream.compat(),
             credentials,
+            algorithms: Box::default(),
+            #[cfg(feature = "json")]
+            parameters: None,
+            multi_authentication: false,
         }
     }
+
+    /// Offers [AuthenticationMethod::IANA_MULTI_AUTHENTICATION_FRAMEWORK], so
+    /// that the server can require several stages. Every stage is performed
+    /// with the methods and credentials of the client.
+    pub const fn with_multi_authentication(mut self, enabled: bool) -> Self {
+        self.multi_authentication = enabled;
+        self
+    }
+
+    /// Offers [AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK] first, sending
+    /// the parameters if the server picks it.
+    #[cfg(feature = "json")]
+    pub fn with_parameters(
+        mut self,
+        parameters: serde_json::Map<String, serde_json::Value>,
+    ) -> Self {
+        self.parameters = Some(parameters);
+        self
+    }
+
+    /// Authenticates with [AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE]
+    /// instead of [AuthenticationMethod::USERNAME_PASSWORD], offering the
+    /// given algorithms in the order of preference. The password is then
+    /// never sent to the server.
+    ///
+    /// An empty list restores the default, i.e. [Algorithm::HMAC_MD5] alone.
+    pub fn with_challenge_handshake<A: Into<Box<[Algorithm]>>>(mut self, algorithms: A) -> Self {
+        self.algorithms = algorithms.into();
+        self
+    }
 }
 
-impl Client<TcpStream, Compat<TcpStream>> for Socks5Client {
-    fn stream(&mut self) -> &mut Compat<TcpStream> {
+impl<S> From<Socks5Client<S>> for Compat<S> {
+    fn from(client: Socks5Client<S>) -> Self {
+        client.stream
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Client<S, Compat<S>> for Socks5Client<S> {
+    fn stream(&mut self) -> &mut Compat<S> {
         &mut self.stream
     }
 
@@ -54,32 +183,410 @@ impl Client<TcpStream, Compat<TcpStream>> for Socks5Client {
         mut self,
         target_addr: Address,
         target_port: u16,
-    ) -> Result<TcpStream, super::ClientError> {
+    ) -> Result<S, super::ClientError> {
+        self.negotiate().await?;
+        self.send_connect_request(target_addr, target_port).await?;
+        Ok(self.stream.into_inner())
+    }
+
+    /// Establishes a [CommandType::BIND] via the SOCKS5 proxy.
+    ///
+    /// Supports [AuthenticationMethod::NO_AUTHENTICATION] and
+    /// [AuthenticationMethod::USERNAME_PASSWORD]. Once
+    /// [BindHandle::accept] succeeds, the underlying [TcpStream] can be
+    /// retrieved with [Compat::into_inner].
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::UnsupportedAuthMethod` if the server requires
+    /// authentication. Other errors can occur if the handshake or
+    /// bind request fails.
+    async fn bind_to_target(
+        mut self,
+        target_addr: Address,
+        target_port: u16,
+    ) -> Result<BindHandle<Compat<S>>, ClientError> {
+        self.negotiate().await?;
+        let (address, port) = self.send_bind_request(target_addr, target_port).await?;
+        Ok(BindHandle::new(self.stream, address, port))
+    }
+
+    /// Establishes a [CommandType::UDP_ASSOCIATE] via the SOCKS5 proxy.
+    ///
+    /// Supports [AuthenticationMethod::NO_AUTHENTICATION] and
+    /// [AuthenticationMethod::USERNAME_PASSWORD]. Over a [TcpStream], the
+    /// association can be handed to [Socks5UdpSocket::new] to relay
+    /// datagrams.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::UnsupportedAuthMethod` if the server requires
+    /// authentication. Other errors can occur if the handshake or the
+    /// request fails.
+    async fn associate_udp(
+        mut self,
+        source_addr: Address,
+        source_port: u16,
+    ) -> Result<UdpAssociation<Compat<S>>, ClientError> {
+        self.negotiate().await?;
+        let (address, port) = self
+            .send_udp_associate_request(source_addr, source_port)
+            .await?;
+        Ok(UdpAssociation::new(self.stream, address, port))
+    }
+}
+
+impl Socks5Client {
+    /// Establishes a connection to a target host via the SOCKS5 proxy with the
+    /// [AuthenticationMethod::IANA_SECURE_SOCKETS_LAYER] method.
+    ///
+    /// The connection to the proxy is upgraded to TLS right after the method
+    /// is chosen, then the credentials are sent inside the tunnel, so they,
+    /// the request and the relayed data are encrypted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::UnsupportedAuthMethod` if the server doesn't
+    /// accept the method, or `ClientError::AuthenticationFailed` if it rejects
+    /// the credentials. Other errors can occur if the TLS handshake or the
+    /// connection request fails.
+    #[cfg(feature = "tls")]
+    pub async fn connect_with_tls_upgrade(
+        mut self,
+        connector: &Connector,
+        server_name: ServerName<'static>,
+        target_addr: Address,
+        target_port: u16,
+    ) -> Result<TlsStream<TcpStream>, ClientError> {
         let choice = self
-            .perform_handshake(
-                [
-                    AuthenticationMethod::NO_AUTHENTICATION,
-                    AuthenticationMethod::USERNAME_PASSWORD,
-                ]
-                .into(),
-            )
+            .perform_handshake([AuthenticationMethod::IANA_SECURE_SOCKETS_LAYER].into())
+            .await?;
+        if choice != AuthenticationMethod::IANA_SECURE_SOCKETS_LAYER {
+            return Err(ClientError::UnsupportedAuthMethod(choice));
+        }
+
+        let stream = connector
+            .connect(server_name, self.stream.into_inner())
             .await?;
+        let mut client = Socks5Client::from_stream(stream, self.credentials);
+        client
+            .authenticate(AuthenticationMethod::USERNAME_PASSWORD)
+            .await?;
+        client
+            .send_connect_request(target_addr, target_port)
+            .await?;
+        Ok(client.stream.into_inner())
+    }
+}
 
-        match choice {
-            | AuthenticationMethod::NO_AUTHENTICATION => {
-                self.send_connect_request(target_addr, target_port).await?;
-                Ok(self.stream.into_inner())
+impl<S: AsyncRead + AsyncWrite + Unpin> Socks5Client<S> {
+    /// Resolves a domain name via the SOCKS5 proxy with the Tor `RESOLVE`
+    /// extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::UnsupportedAuthMethod` if the server requires
+    /// authentication. Other errors can occur if the handshake or the
+    /// request fails.
+    pub async fn resolve(mut self, domain: &str) -> Result<Address, ClientError> {
+        self.negotiate().await?;
+        self.send_resolve_request(domain.into()).await
+    }
+
+    /// Resolves an IP address to a domain name via the SOCKS5 proxy with the
+    /// Tor `RESOLVE_PTR` extension.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::UnsupportedAuthMethod` if the server requires
+    /// authentication. Other errors can occur if the handshake or the
+    /// request fails.
+    pub async fn resolve_ptr(mut self, address: IpAddr) -> Result<Address, ClientError> {
+        self.negotiate().await?;
+        self.send_resolve_ptr_request(address).await
+    }
+
+    /// Establishes a connection to a target host via the SOCKS5 proxy with the
+    /// [AuthenticationMethod::GSSAPI] method.
+    ///
+    /// The security context is established with the mechanism for the
+    /// `service` name of the proxy, and the first of `levels` is requested.
+    /// The returned stream encapsulates the data according to the negotiated
+    /// protection level.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::UnsupportedAuthMethod` if the server doesn't
+    /// accept the method, or `ClientError::AuthenticationFailed` if the
+    /// exchange fails. Other errors can occur if the connection request
+    /// fails.
+    pub async fn connect_with_gssapi(
+        mut self,
+        mechanism: &dyn Mechanism,
+        service: &str,
+        levels: &[ProtectionLevel],
+        target_addr: Address,
+        target_port: u16,
+    ) -> Result<EncapsulatedStream<Compat<S>>, ClientError> {
+        let choice = self
+            .perform_handshake([AuthenticationMethod::GSSAPI].into())
+            .await?;
+        if choice != AuthenticationMethod::GSSAPI {
+            return Err(ClientError::UnsupportedAuthMethod(choice));
+        }
+
+        let session = gssapi_auth_impl(&mut self, mechanism, service, levels).await?;
+        let mut stream = session.encapsulate(self.stream);
+
+        let request = Request {
+            command: CommandType::CONNECT,
+            address: target_addr,
+            port:    target_port,
+        };
+        request.write_to(&mut stream).await?;
+
+        let response = Response::read_from(&mut stream).await?;
+        if !response.reply.is_success() {
+            return Err(ClientError::RequestFailed(response.reply));
+        }
+
+        Ok(stream)
+    }
+
+    /// Performs the handshake and the authentication sub-negotiation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::UnsupportedAuthMethod` if the server chose a
+    /// method the client doesn't support, or
+    /// `ClientError::AuthenticationFailed` if the server rejected the
+    /// credentials.
+    async fn negotiate(&mut self) -> Result<(), ClientError> {
+        let method = if self.algorithms.is_empty() {
+            AuthenticationMethod::USERNAME_PASSWORD
+        } else {
+            AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE
+        };
+        #[cfg(feature = "json")]
+        let json = self
+            .parameters
+            .is_some()
+            .then_some(AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK);
+        #[cfg(not(feature = "json"))]
+        let json = None;
+        let multi = self
+            .multi_authentication
+            .then_some(AuthenticationMethod::IANA_MULTI_AUTHENTICATION_FRAMEWORK);
+        let offered: Vec<_> = json
+            .into_iter()
+            .chain(multi)
+            .chain([AuthenticationMethod::NO_AUTHENTICATION, method])
+            .collect();
+
+        let choice = self.perform_handshake(offered.clone().into()).await?;
+        if !offered.contains(&choice) {
+            return Err(ClientError::UnsupportedAuthMethod(choice));
+        }
+
+        if choice != AuthenticationMethod::IANA_MULTI_AUTHENTICATION_FRAMEWORK {
+            return self.authenticate(choice).await;
+        }
+        while let Some(stage) = read_stage_status_impl(self).await? {
+            self.authenticate(stage).await?;
+        }
+        Ok(())
+    }
+
+    /// Performs the sub-negotiation of a single method.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ClientError::UnsupportedAuthMethod` if the method is not
+    /// supported, or `ClientError::AuthenticationFailed` if the server
+    /// rejected the credentials.
+    async fn authenticate(&mut self, method: AuthenticationMethod) -> Result<(), ClientError> {
+        match method {
+            | AuthenticationMethod::NO_AUTHENTICATION => Ok(()),
+            #[cfg(feature = "json")]
+            | AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK => {
+                let parameters = self.parameters.clone().unwrap_or_default();
+                json_parameters_auth_impl(self, &parameters).await
+            },
+            | AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE => {
+                let username = self.credentials.0.clone();
+                let credentials = sync::Arc::clone(&self.credentials);
+                let algorithms = if self.algorithms.is_empty() {
+                    DEFAULT_ALGORITHMS.into()
+                } else {
+                    self.algorithms.clone()
+                };
+                chap_auth_impl(self, username, &credentials.1, &algorithms).await
             },
             | AuthenticationMethod::USERNAME_PASSWORD => {
                 let username = self.credentials.0.clone();
                 let password = self.credentials.1.clone();
-                username_password_auth_impl(&mut self, username, password).await?;
-                self.send_connect_request(target_addr, target_port).await?;
-                Ok(self.stream.into_inner())
+                username_password_auth_impl(self, username, password).await
             },
-            | _ => {
-                return Err(ClientError::UnsupportedAuthMethod(choice));
+            | _ => Err(ClientError::UnsupportedAuthMethod(method)),
+        }
+    }
+}
+
+/// A UDP socket relaying datagrams through a SOCKS5 proxy.
+///
+/// Datagrams are wrapped into and unwrapped from the SOCKS UDP request header
+/// transparently. Fragmented datagrams are reassembled.
+pub struct Socks5UdpSocket {
+    socket:      UdpSocket,
+    control:     TcpStream,
+    reassembler: Reassembler,
+    buffer:      Box<[u8]>,
+}
+
+impl Socks5UdpSocket {
+    /// Opens a UDP socket relaying datagrams through the association, see
+    /// [Client::associate_udp].
+    ///
+    /// The socket keeps the connection to the proxy open for as long as it
+    /// lives.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the relay address can't be resolved or the
+    /// socket can't be opened.
+    pub async fn new(association: UdpAssociation<Compat<TcpStream>>) -> Result<Self, ClientError> {
+        let relay_port = association.port();
+        let relay_addr = association.address().clone();
+        let control = association.into_stream().into_inner();
+
+        let relay_ip = match relay_addr {
+            | Address::Ipv4(addr) if !addr.is_unspecified() => IpAddr::V4(addr),
+            | Address::Ipv6(addr) if !addr.is_unspecified() => IpAddr::V6(addr),
+            | Address::Domain(domain) => {
+                let domain = String::from_utf8_lossy(&domain).into_owned();
+                lookup_host((domain, relay_port))
+                    .await?
+                    .next()
+                    .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::AddrNotAvailable))?
+                    .ip()
             },
+            | _ => control.peer_addr()?.ip(),
+        };
+        let relay = SocketAddr::new(relay_ip, relay_port);
+
+        let local_ip = control.local_addr()?.ip();
+        let socket = UdpSocket::bind((local_ip, 0)).await?;
+        socket.connect(relay).await?;
+
+        Ok(Self {
+            socket,
+            control,
+            reassembler: Reassembler::new(reassembly::Config::default()),
+            buffer: vec![0; usize::from(u16::MAX)].into_boxed_slice(),
+        })
+    }
+
+    /// Sets the limits for reassembling fragmented datagrams.
+    pub fn with_reassembly(mut self, config: reassembly::Config) -> Self {
+        self.reassembler = Reassembler::new(config);
+        self
+    }
+
+    /// Returns the address of the proxy's UDP relay.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the socket is not connected.
+    pub fn relay_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    /// Returns the local address of the underlying UDP socket.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the address can't be retrieved.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Returns the connection to the proxy that keeps the association alive.
+    pub const fn control_stream(&self) -> &TcpStream {
+        &self.control
+    }
+
+    /// Sends a datagram to the target through the proxy.
+    ///
+    /// On success, returns the number of payload bytes sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if the target's domain name is longer than 255
+    /// bytes or an I/O error occurs.
+    pub async fn send_to(
+        &self,
+        payload: &[u8],
+        target_addr: Address,
+        target_port: u16,
+    ) -> Result<usize, ClientError> {
+        let header = UdpHeader {
+            fragment: 0,
+            address:  target_addr,
+            port:     target_port,
+        };
+
+        let mut packet = Vec::with_capacity(payload.len().saturating_add(262));
+        header.encode_into(&mut packet)?;
+        packet.extend_from_slice(payload);
+
+        self.socket.send(&packet).await?;
+        Ok(payload.len())
+    }
+
+    /// Receives a datagram relayed by the proxy.
+    ///
+    /// On success, returns the number of bytes read alongside with the
+    /// address and port of the datagram's origin. Excess bytes are discarded
+    /// if the buffer is too small. Malformed datagrams are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `ClientError` if an I/O error occurs, or
+    /// [std::io::ErrorKind::ConnectionAborted] if the proxy closed the
+    /// connection, which ends the association.
+    pub async fn recv_from(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, Address, u16), ClientError> {
+        let mut control_buf = [0; 1];
+        loop {
+            let received = tokio::select! {
+                read = self.control.read(&mut control_buf) => {
+                    if read? == 0 {
+                        let closed = std::io::Error::from(std::io::ErrorKind::ConnectionAborted);
+                        return Err(closed.into());
+                    }
+                    continue;
+                },
+                received = self.socket.recv(&mut self.buffer) => received?,
+            };
+            let Some(datagram) = self.buffer.get(.. received) else {
+                continue;
+            };
+            let Ok((header, payload)) = UdpHeader::parse(datagram) else {
+                continue;
+            };
+            let Some(complete) = self.reassembler.push(&header, payload) else {
+                continue;
+            };
+
+            let copied = complete.data.len().min(buffer.len());
+            if let (Some(head), Some(data)) =
+                (buffer.get_mut(.. copied), complete.data.get(.. copied))
+            {
+                head.copy_from_slice(data);
+            }
+            return Ok((copied, complete.header.address, complete.header.port));
         }
     }
 }
@@ -0,0 +1,307 @@
+//! The GSS-API plumbing of the
+//! [AuthenticationMethod::GSSAPI](crate::socks5::proto::AuthenticationMethod::GSSAPI)
+//! method.
+//!
+//! [RFC 1961](https://www.rfc-editor.org/rfc/rfc1961) doesn't tie SOCKS to a
+//! particular GSS-API mechanism, so the security services are provided by a
+//! [Mechanism] implementation, e.g. a binding to the system's Kerberos
+//! library. Once the security context is established and the protection level
+//! is negotiated, the rest of the session goes through an
+//! [EncapsulatedStream].
+//!
+//! The encapsulation of UDP datagrams from section 5 of the RFC is not
+//! implemented, so `UDP ASSOCIATE` is not available on GSS-API sessions.
+
+use std::{
+    io::{
+        Error as IoError,
+        ErrorKind,
+        Result as IoResult,
+    },
+    task::{
+        Context,
+        Poll,
+        ready,
+    },
+};
+
+use futures::{
+    AsyncRead,
+    AsyncWrite,
+};
+
+use crate::socks5::proto::{
+    ConversionError,
+    messages::auth::gssapi::{
+        Message,
+        MessageType,
+        ProtectionLevel,
+    },
+};
+
+/// The amount of plaintext wrapped into a single message. It leaves room for
+/// the overhead of the mechanism, as a message can't exceed 65535 bytes.
+const MAX_CHUNK: usize = 16 * 1024;
+
+/// An error reported by a [Mechanism] or a [SecurityContext].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MechanismError(pub Box<str>);
+
+impl From<MechanismError> for IoError {
+    fn from(value: MechanismError) -> Self {
+        IoError::new(ErrorKind::InvalidData, String::from(value.0))
+    }
+}
+
+/// A GSS-API mechanism, e.g. Kerberos V5.
+pub trait Mechanism: Send + Sync {
+    /// Creates a security context for the server side.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [MechanismError] if the server's credentials can't be
+    /// acquired.
+    fn accept(&self) -> Result<Box<dyn SecurityContext>, MechanismError>;
+
+    /// Creates a security context for the client side, targeting the given
+    /// service name, e.g. `rcmd@proxy.example.com`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [MechanismError] if the client's credentials can't be
+    /// acquired or the name is not valid.
+    fn initiate(&self, target: &str) -> Result<Box<dyn SecurityContext>, MechanismError>;
+}
+
+/// A security context, i.e. one end of a GSS-API session.
+pub trait SecurityContext: Send {
+    /// Processes a token received from the peer and returns the token to send
+    /// back, if any.
+    ///
+    /// The client side is stepped with `None` first, to produce the initial
+    /// token.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [MechanismError] if the token is rejected.
+    fn step(&mut self, token: Option<&[u8]>) -> Result<Option<Box<[u8]>>, MechanismError>;
+
+    /// Returns `true` once the context is established.
+    fn is_established(&self) -> bool;
+
+    /// Protects a message for the peer, encrypting it if `confidential` is
+    /// `true`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [MechanismError] if the message can't be protected.
+    fn wrap(&mut self, message: &[u8], confidential: bool) -> Result<Box<[u8]>, MechanismError>;
+
+    /// Verifies and decrypts a message protected by the peer.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [MechanismError] if the message can't be verified.
+    fn unwrap(&mut self, token: &[u8]) -> Result<Box<[u8]>, MechanismError>;
+
+    /// Returns the authenticated name of the peer, if known.
+    fn peer_name(&self) -> Option<Box<[u8]>>;
+}
+
+/// An established security context alongside with the negotiated
+/// protection level.
+pub struct Session {
+    context: Box<dyn SecurityContext>,
+    level:   ProtectionLevel,
+}
+
+impl Session {
+    pub fn new(context: Box<dyn SecurityContext>, level: ProtectionLevel) -> Self {
+        Self {
+            context,
+            level,
+        }
+    }
+
+    pub const fn level(&self) -> ProtectionLevel {
+        self.level
+    }
+
+    /// Returns the authenticated name of the peer, if known.
+    pub fn peer_name(&self) -> Option<Box<[u8]>> {
+        self.context.peer_name()
+    }
+
+    /// Wraps the stream so that everything written to or read from it is
+    /// protected by the session.
+    pub const fn encapsulate<S>(self, stream: S) -> EncapsulatedStream<S> {
+        EncapsulatedStream {
+            inner:     stream,
+            session:   self,
+            pending:   Vec::new(),
+            outgoing:  Vec::new(),
+            written:   0,
+            incoming:  Vec::new(),
+            plaintext: Vec::new(),
+            consumed:  0,
+        }
+    }
+}
+
+/// A stream which carries the data in [MessageType::ENCAPSULATION] messages,
+/// as described in section 5 of RFC 1961.
+///
+/// Written data is buffered and sent when the stream is flushed, before it
+/// is read from, or once the buffer is full.
+pub struct EncapsulatedStream<S> {
+    inner:     S,
+    session:   Session,
+    pending:   Vec<u8>,
+    outgoing:  Vec<u8>,
+    written:   usize,
+    incoming:  Vec<u8>,
+    plaintext: Vec<u8>,
+    consumed:  usize,
+}
+
+impl<S> EncapsulatedStream<S> {
+    pub const fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub const fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: AsyncWrite + Unpin> EncapsulatedStream<S> {
+    /// Wraps the buffered data and writes it to the inner stream.
+    fn poll_drain(&mut self, context: &mut Context<'_>) -> Poll<IoResult<()>> {
+        loop {
+            while let Some(remaining) = self.outgoing.get(self.written ..) {
+                if remaining.is_empty() {
+                    break;
+                }
+                let written =
+                    ready!(std::pin::Pin::new(&mut self.inner).poll_write(context, remaining))?;
+                if written == 0 {
+                    return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+                }
+                self.written = self.written.saturating_add(written);
+            }
+            self.outgoing.clear();
+            self.written = 0;
+
+            if self.pending.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let confidential = self.session.level.is_confidential();
+            let token = self.session.context.wrap(&self.pending, confidential)?;
+            self.pending.clear();
+            Message::new(MessageType::ENCAPSULATION, token)
+                .encode_into(&mut self.outgoing)
+                .map_err(to_io_error)?;
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for EncapsulatedStream<S> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<IoResult<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+
+        loop {
+            if let Some(available) = this.plaintext.get(this.consumed ..) {
+                if !available.is_empty() {
+                    let copied = available.len().min(buf.len());
+                    if let (Some(head), Some(data)) =
+                        (buf.get_mut(.. copied), available.get(.. copied))
+                    {
+                        head.copy_from_slice(data);
+                    }
+                    this.consumed = this.consumed.saturating_add(copied);
+                    return Poll::Ready(Ok(copied));
+                }
+            }
+
+            let parsed = Message::parse(&this.incoming).map_err(to_io_error)?;
+            if let Some((message, length)) = parsed {
+                this.incoming.drain(.. length);
+                match message.message_type {
+                    | MessageType::ENCAPSULATION => {
+                        this.plaintext = this.session.context.unwrap(&message.token)?.into_vec();
+                        this.consumed = 0;
+                        continue;
+                    },
+                    | MessageType::ABORT => {
+                        return Poll::Ready(Err(ErrorKind::ConnectionAborted.into()));
+                    },
+                    | _ => return Poll::Ready(Err(ErrorKind::InvalidData.into())),
+                }
+            }
+
+            let mut chunk = [0_u8; 4096];
+            let read = ready!(std::pin::Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            let Some(data) = chunk.get(.. read) else {
+                return Poll::Ready(Err(ErrorKind::InvalidData.into()));
+            };
+            if data.is_empty() {
+                return if this.incoming.is_empty() {
+                    Poll::Ready(Ok(0))
+                } else {
+                    Poll::Ready(Err(ErrorKind::UnexpectedEof.into()))
+                };
+            }
+            this.incoming.extend_from_slice(data);
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for EncapsulatedStream<S> {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<IoResult<usize>> {
+        let this = self.get_mut();
+        if this.pending.len() >= MAX_CHUNK {
+            ready!(this.poll_drain(cx))?;
+        }
+
+        let accepted = buf.len().min(MAX_CHUNK.saturating_sub(this.pending.len()));
+        if let Some(data) = buf.get(.. accepted) {
+            this.pending.extend_from_slice(data);
+        }
+        Poll::Ready(Ok(accepted))
+    }
+
+    fn poll_flush(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        std::pin::Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        std::pin::Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+fn to_io_error(error: ConversionError) -> IoError {
+    match error {
+        | ConversionError::IoError(error) => error,
+        | ConversionError::InvalidProtocolVersion(_) | ConversionError::MalformedMessage => {
+            ErrorKind::InvalidData.into()
+        },
+    }
+}
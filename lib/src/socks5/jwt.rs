@@ -0,0 +1,111 @@
+//! Signed tokens carried in the password of the
+//! [AuthenticationMethod::USERNAME_PASSWORD](crate::socks5::proto::AuthenticationMethod::USERNAME_PASSWORD)
+//! method, backed by [jsonwebtoken].
+//!
+//! Clients which only speak RFC 1929 can thus present short-lived
+//! credentials issued elsewhere, e.g. JSON Web Tokens signed with HS256,
+//! ES256 or EdDSA, without a user database on the server. The password is
+//! limited to 255 bytes, so tokens are expected to keep their claims short.
+
+use std::io::{
+    Error as IoError,
+    ErrorKind,
+    Result as IoResult,
+};
+
+pub use jsonwebtoken;
+use jsonwebtoken::{
+    Algorithm,
+    DecodingKey,
+    Validation,
+};
+
+/// The claims of a verified token.
+pub type Claims = serde_json::Map<String, serde_json::Value>;
+
+/// Verifies tokens against a set of locally configured keys.
+///
+/// A token is accepted if its signature matches one of the keys of its
+/// algorithm, it has an unexpired `exp` claim, its `nbf` claim, if any, has
+/// passed and, if audiences are configured, its `aud` claim names one of
+/// them.
+#[derive(Clone, Default)]
+pub struct TokenVerifier {
+    keys:      Vec<(Algorithm, DecodingKey)>,
+    audiences: Box<[Box<str>]>,
+    leeway:    u64,
+}
+
+impl TokenVerifier {
+    /// Creates a verifier without keys, which rejects every token.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accepts the tokens signed with HS256 and the secret.
+    pub fn with_hmac_secret(mut self, secret: &[u8]) -> Self {
+        self.keys
+            .push((Algorithm::HS256, DecodingKey::from_secret(secret)));
+        self
+    }
+
+    /// Accepts the tokens signed with ES256 and the private key matching the
+    /// PEM-encoded P-256 public key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the key is malformed.
+    pub fn with_ec_public_key(mut self, pem_key: &[u8]) -> IoResult<Self> {
+        let decoding_key = DecodingKey::from_ec_pem(pem_key)
+            .map_err(|error| IoError::new(ErrorKind::InvalidData, error))?;
+        self.keys.push((Algorithm::ES256, decoding_key));
+        Ok(self)
+    }
+
+    /// Accepts the tokens signed with EdDSA and the private key matching the
+    /// PEM-encoded Ed25519 public key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the key is malformed.
+    pub fn with_ed_public_key(mut self, pem_key: &[u8]) -> IoResult<Self> {
+        let decoding_key = DecodingKey::from_ed_pem(pem_key)
+            .map_err(|error| IoError::new(ErrorKind::InvalidData, error))?;
+        self.keys.push((Algorithm::EdDSA, decoding_key));
+        Ok(self)
+    }
+
+    /// Only accepts the tokens intended for one of the audiences.
+    pub fn with_audiences<A: Into<Box<[Box<str>]>>>(mut self, audiences: A) -> Self {
+        self.audiences = audiences.into();
+        self
+    }
+
+    /// Sets how many seconds of clock skew are tolerated when checking the
+    /// `exp` and `nbf` claims.
+    pub const fn with_leeway(mut self, seconds: u64) -> Self {
+        self.leeway = seconds;
+        self
+    }
+
+    /// Returns the claims of the token, or `None` if it's rejected.
+    pub fn verify(&self, token: &[u8]) -> Option<Claims> {
+        let token = std::str::from_utf8(token).ok()?;
+        let header = jsonwebtoken::decode_header(token).ok()?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = self.leeway;
+        validation.validate_nbf = true;
+        if self.audiences.is_empty() {
+            validation.validate_aud = false;
+        } else {
+            validation.set_audience(&self.audiences);
+        }
+
+        self.keys
+            .iter()
+            .filter(|&&(algorithm, _)| algorithm == header.alg)
+            .find_map(|entry| jsonwebtoken::decode::<Claims>(token, &entry.1, &validation).ok())
+            .map(|token| token.claims)
+    }
+}
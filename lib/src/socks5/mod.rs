@@ -1,5 +1,11 @@
 //! Things for protocol version 5.
 
+pub mod chap;
 pub mod client;
+pub mod gssapi;
+#[cfg(feature = "jwt")]
+pub mod jwt;
 pub mod proto;
 pub mod server;
+#[cfg(feature = "tls")]
+pub mod tls;
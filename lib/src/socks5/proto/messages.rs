@@ -149,7 +149,10 @@ pub struct Response {
 }
 
 impl Response {
+    pub const GENERAL_FAILURE: Response = Self::new_error(Reply::GENERAL_FAILURE);
     pub const HOST_UNREACHABLE: Response = Self::new_error(Reply::HOST_UNREACHABLE);
+    pub const NOT_ALLOWED_BY_RULESET: Response =
+        Self::new_error(Reply::CONNECTION_NOT_ALLOWED_BY_RULESET);
     pub const UNSUPPORTED_COMMAND: Response = Self::new_error(Reply::COMMAND_NOT_SUPPORTED);
 
     const fn new_error(reply: Reply) -> Self {
@@ -206,6 +209,168 @@ impl Decoder<ConversionError> for Response {
     }
 }
 
+/// The header prepended to every datagram relayed through a
+/// [CommandType::UDP_ASSOCIATE] association.
+///
+/// Besides the async [Encoder] and [Decoder], the header can be encoded to
+/// and parsed from a buffer synchronously, as datagrams arrive as a whole.
+#[derive(Debug, Clone)]
+pub struct UdpHeader {
+    pub fragment: u8,
+    pub address:  Address,
+    pub port:     u16,
+}
+
+impl UdpHeader {
+    /// Appends the wire representation of the header to the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConversionError::MalformedMessage] if the domain name is
+    /// longer than 255 bytes, in which case the buffer is left untouched.
+    pub fn encode_into(&self, buffer: &mut Vec<u8>) -> Result<(), ConversionError> {
+        let start = buffer.len();
+        buffer.extend_from_slice(&[0x00, 0x00]); // RSV
+        buffer.push(self.fragment);
+
+        if let Err(error) = self.address.encode_into(buffer) {
+            buffer.truncate(start);
+            return Err(error);
+        }
+        buffer.extend_from_slice(&self.port.to_be_bytes());
+        Ok(())
+    }
+
+    /// Parses a header from the beginning of the buffer.
+    ///
+    /// Returns the header alongside with the rest of the buffer, which is the
+    /// datagram's payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConversionError::MalformedMessage] if the buffer is too short
+    /// or the address type is unknown.
+    pub fn parse(buffer: &[u8]) -> Result<(Self, &[u8]), ConversionError> {
+        let (&[_, _, fragment], rest) = buffer
+            .split_first_chunk::<3>()
+            .ok_or(ConversionError::MalformedMessage)?;
+
+        let (address, rest) = Address::parse(rest)?;
+
+        let (port_buf, rest) = rest
+            .split_first_chunk::<2>()
+            .ok_or(ConversionError::MalformedMessage)?;
+        let port = u16::from_be_bytes(*port_buf);
+
+        Ok((
+            Self {
+                fragment,
+                address,
+                port,
+            },
+            rest,
+        ))
+    }
+}
+
+impl Encoder<ConversionError> for UdpHeader {
+    async fn write_to<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<(), ConversionError> {
+        writer.write_all(&[0x00, 0x00]).await?; // RSV
+
+        writer.write_all(&[self.fragment]).await?;
+
+        self.address.write_to(writer).await?;
+        writer.write_all(&self.port.to_be_bytes()).await?;
+
+        Ok(())
+    }
+}
+
+impl Decoder<ConversionError> for UdpHeader {
+    async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self, ConversionError> {
+        // skip RSV bytes
+        let mut rsv_buf = [0_u8; 2];
+        reader.read_exact(&mut rsv_buf).await?;
+
+        let mut frag_buf = [0_u8; 1];
+        reader.read_exact(&mut frag_buf).await?;
+        let fragment = frag_buf[0];
+
+        let address = Address::read_from(reader).await?;
+
+        let mut port_buf = [0_u8; 2];
+        reader.read_exact(&mut port_buf).await?;
+        let port = u16::from_be_bytes(port_buf);
+
+        Ok(UdpHeader {
+            fragment,
+            address,
+            port,
+        })
+    }
+}
+
+/// A datagram relayed through a [CommandType::UDP_ASSOCIATE] association.
+///
+/// The [Decoder] implementation reads the payload until the end of the
+/// reader, so it is expected to be given a single datagram.
+#[derive(Debug, Clone)]
+pub struct UdpDatagram {
+    pub header: UdpHeader,
+    pub data:   Box<[u8]>,
+}
+
+impl UdpDatagram {
+    /// Returns the wire representation of the datagram.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConversionError::MalformedMessage] if the domain name is
+    /// longer than 255 bytes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConversionError> {
+        let mut buffer = Vec::with_capacity(self.data.len().saturating_add(262));
+        self.header.encode_into(&mut buffer)?;
+        buffer.extend_from_slice(&self.data);
+        Ok(buffer)
+    }
+
+    /// Parses a whole datagram.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConversionError::MalformedMessage] if the header is malformed.
+    pub fn parse(buffer: &[u8]) -> Result<Self, ConversionError> {
+        let (header, data) = UdpHeader::parse(buffer)?;
+        Ok(Self {
+            header,
+            data: data.into(),
+        })
+    }
+}
+
+impl Encoder<ConversionError> for UdpDatagram {
+    async fn write_to<W: AsyncWrite + Unpin>(&self, writer: &mut W) -> Result<(), ConversionError> {
+        self.header.write_to(writer).await?;
+        writer.write_all(&self.data).await?;
+
+        Ok(())
+    }
+}
+
+impl Decoder<ConversionError> for UdpDatagram {
+    async fn read_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Self, ConversionError> {
+        let header = UdpHeader::read_from(reader).await?;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await?;
+
+        Ok(UdpDatagram {
+            header,
+            data: data.into_boxed_slice(),
+        })
+    }
+}
+
 pub mod auth {
     use super::*;
 
@@ -305,4 +470,463 @@ pub mod auth {
             }
         }
     }
+
+    /// Messages of the [AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE]
+    /// sub-negotiation, as described in
+    /// [draft-ietf-aft-socks-chap](https://datatracker.ietf.org/doc/html/draft-ietf-aft-socks-chap-01).
+    ///
+    /// Each message is a list of attribute/value pairs.
+    pub mod chap {
+        use super::*;
+
+        pub const AUTH_VERSION: u8 = 0x01;
+
+        caret_int! {
+            pub struct AttributeType(u8) {
+                STATUS = 0x00,
+                TEXT_MESSAGE = 0x01,
+                USER_IDENTITY = 0x02,
+                CHALLENGE = 0x03,
+                RESPONSE = 0x04,
+                CHARSET = 0x05,
+                IDENTIFIER = 0x10,
+                ALGORITHMS = 0x11,
+            }
+        }
+
+        caret_int! {
+            pub struct Algorithm(u8) {
+                HMAC_MD5 = 0x85,
+            }
+        }
+
+        impl Algorithm {
+            /// HMAC-SHA-256, an extension of this crate.
+            ///
+            /// **Non-standard:** the draft only defines [Algorithm::HMAC_MD5],
+            /// so other implementations don't know this value. It's never
+            /// offered nor accepted by default, only when listed explicitly
+            /// on both ends.
+            pub const NON_STANDARD_HMAC_SHA256: Self = Self(0x86);
+        }
+
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct Attribute {
+            pub attribute_type: AttributeType,
+            pub value:          Box<[u8]>,
+        }
+
+        impl Attribute {
+            pub fn new<V: Into<Box<[u8]>>>(attribute_type: AttributeType, value: V) -> Self {
+                Self {
+                    attribute_type,
+                    value: value.into(),
+                }
+            }
+        }
+
+        #[derive(Debug, Clone, Default)]
+        pub struct Message {
+            pub attributes: Vec<Attribute>,
+        }
+
+        impl Message {
+            pub fn new<A: Into<Vec<Attribute>>>(attributes: A) -> Self {
+                Self {
+                    attributes: attributes.into(),
+                }
+            }
+
+            /// A message carrying only a [AttributeType::STATUS] attribute.
+            pub fn status(status: Status) -> Self {
+                Self::new([Attribute::new(AttributeType::STATUS, [status.into()])])
+            }
+
+            /// Returns the value of the first attribute of the given type.
+            pub fn get(&self, attribute_type: AttributeType) -> Option<&[u8]> {
+                self.attributes
+                    .iter()
+                    .find(|attribute| attribute.attribute_type == attribute_type)
+                    .map(|attribute| &*attribute.value)
+            }
+
+            /// Returns the [AttributeType::STATUS] of the message, if any.
+            pub fn get_status(&self) -> Option<Status> {
+                match *self.get(AttributeType::STATUS)? {
+                    | [status] => Some(Status::from(status)),
+                    | _ => Some(Status::FAILURE),
+                }
+            }
+        }
+
+        impl Encoder<ConversionError> for Message {
+            async fn write_to<W: AsyncWrite + Unpin>(
+                &self,
+                writer: &mut W,
+            ) -> Result<(), ConversionError> {
+                let Ok(count) = u8::try_from(self.attributes.len()) else {
+                    return Err(ConversionError::MalformedMessage);
+                };
+
+                let mut message = vec![AUTH_VERSION, count];
+                for attribute in &self.attributes {
+                    let Ok(length) = u8::try_from(attribute.value.len()) else {
+                        return Err(ConversionError::MalformedMessage);
+                    };
+                    message.extend_from_slice(&[attribute.attribute_type.0, length]);
+                    message.extend_from_slice(&attribute.value);
+                }
+
+                writer.write_all(&message).await?;
+                Ok(())
+            }
+        }
+
+        impl Decoder<ConversionError> for Message {
+            async fn read_from<R: AsyncRead + Unpin>(
+                reader: &mut R,
+            ) -> Result<Self, ConversionError> {
+                let mut header = [0_u8; 2];
+                reader.read_exact(&mut header).await?;
+                if header[0] != AUTH_VERSION {
+                    return Err(ConversionError::InvalidProtocolVersion(header[0]));
+                }
+
+                let mut attributes = Vec::with_capacity(usize::from(header[1]));
+                for _ in 0 .. header[1] {
+                    let mut attribute_header = [0_u8; 2];
+                    reader.read_exact(&mut attribute_header).await?;
+                    let mut value = vec![0_u8; usize::from(attribute_header[1])];
+                    reader.read_exact(&mut value).await?;
+                    attributes.push(Attribute::new(AttributeType(attribute_header[0]), value));
+                }
+
+                Ok(Self {
+                    attributes,
+                })
+            }
+        }
+    }
+
+    /// Messages of the [AuthenticationMethod::GSSAPI] sub-negotiation, as
+    /// described in [RFC 1961](https://www.rfc-editor.org/rfc/rfc1961).
+    pub mod gssapi {
+        use super::*;
+
+        pub const AUTH_VERSION: u8 = 0x01;
+
+        caret_int! {
+            pub struct MessageType(u8) {
+                AUTHENTICATION = 0x01,
+                PROTECTION_LEVEL = 0x02,
+                ENCAPSULATION = 0x03,
+                ABORT = 0xFF,
+            }
+        }
+
+        caret_int! {
+            pub struct ProtectionLevel(u8) {
+                INTEGRITY = 0x01,
+                CONFIDENTIALITY = 0x02,
+                SELECTIVE = 0x03,
+            }
+        }
+
+        impl ProtectionLevel {
+            /// Returns `true` if the messages are to be encrypted.
+            ///
+            /// The selective level leaves the choice to the local
+            /// configuration; this crate encrypts them.
+            #[inline]
+            pub fn is_confidential(&self) -> bool {
+                *self != Self::INTEGRITY
+            }
+        }
+
+        /// A framed token. The token of an [MessageType::ABORT] message is
+        /// always empty, as the message carries none.
+        #[derive(Debug, Clone)]
+        pub struct Message {
+            pub message_type: MessageType,
+            pub token:        Box<[u8]>,
+        }
+
+        impl Message {
+            pub fn new<T: Into<Box<[u8]>>>(message_type: MessageType, token: T) -> Self {
+                Self {
+                    message_type,
+                    token: token.into(),
+                }
+            }
+
+            pub fn abort() -> Self {
+                Self::new(MessageType::ABORT, [])
+            }
+
+            /// Appends the wire form of the message to the buffer.
+            ///
+            /// # Errors
+            ///
+            /// Returns [ConversionError::MalformedMessage] if the token is
+            /// longer than 65535 bytes.
+            pub fn encode_into(&self, buffer: &mut Vec<u8>) -> Result<(), ConversionError> {
+                if self.message_type == MessageType::ABORT {
+                    buffer.extend_from_slice(&[AUTH_VERSION, self.message_type.0]);
+                    return Ok(());
+                }
+
+                let Ok(length) = u16::try_from(self.token.len()) else {
+                    return Err(ConversionError::MalformedMessage);
+                };
+                buffer.extend_from_slice(&[AUTH_VERSION, self.message_type.0]);
+                buffer.extend_from_slice(&length.to_be_bytes());
+                buffer.extend_from_slice(&self.token);
+                Ok(())
+            }
+
+            /// Parses a message from the start of the buffer.
+            ///
+            /// Returns `Ok(None)` if the buffer doesn't hold a whole message
+            /// yet, or the message alongside with the number of bytes it
+            /// takes.
+            ///
+            /// # Errors
+            ///
+            /// Returns a [ConversionError] if the version is not supported.
+            pub fn parse(buffer: &[u8]) -> Result<Option<(Self, usize)>, ConversionError> {
+                let Some(&[version, message_type]) = buffer.get(.. 2) else {
+                    return Ok(None);
+                };
+                let message_type = MessageType(message_type);
+                if version != AUTH_VERSION {
+                    return Err(ConversionError::InvalidProtocolVersion(version));
+                }
+                if message_type == MessageType::ABORT {
+                    return Ok(Some((Self::abort(), 2)));
+                }
+
+                let Some(&[length_high, length_low]) = buffer.get(2 .. 4) else {
+                    return Ok(None);
+                };
+                let message_length =
+                    usize::from(u16::from_be_bytes([length_high, length_low])).saturating_add(4);
+                let Some(token) = buffer.get(4 .. message_length) else {
+                    return Ok(None);
+                };
+                Ok(Some((Self::new(message_type, token), message_length)))
+            }
+        }
+
+        impl Encoder<ConversionError> for Message {
+            async fn write_to<W: AsyncWrite + Unpin>(
+                &self,
+                writer: &mut W,
+            ) -> Result<(), ConversionError> {
+                let mut message = Vec::with_capacity(self.token.len().saturating_add(4));
+                self.encode_into(&mut message)?;
+                writer.write_all(&message).await?;
+                Ok(())
+            }
+        }
+
+        impl Decoder<ConversionError> for Message {
+            async fn read_from<R: AsyncRead + Unpin>(
+                reader: &mut R,
+            ) -> Result<Self, ConversionError> {
+                let mut header = [0_u8; 2];
+                reader.read_exact(&mut header).await?;
+                if header[0] != AUTH_VERSION {
+                    return Err(ConversionError::InvalidProtocolVersion(header[0]));
+                }
+                let message_type = MessageType(header[1]);
+                if message_type == MessageType::ABORT {
+                    return Ok(Self::abort());
+                }
+
+                let mut length_buf = [0_u8; 2];
+                reader.read_exact(&mut length_buf).await?;
+                let mut token = vec![0_u8; usize::from(u16::from_be_bytes(length_buf))];
+                reader.read_exact(&mut token).await?;
+
+                Ok(Self::new(message_type, token))
+            }
+        }
+    }
+
+    /// Messages of the
+    /// [AuthenticationMethod::IANA_MULTI_AUTHENTICATION_FRAMEWORK]
+    /// sub-negotiation.
+    ///
+    /// The server runs a sequence of stages, each being the sub-negotiation of
+    /// another method. It announces the first stage, then reports the status
+    /// of every stage alongside with the next one.
+    ///
+    /// No specification describes the framing of the statuses, so this crate
+    /// uses its own, modeled after the other sub-negotiations: a
+    /// [StageStatus](multi_authentication::StageStatus) is the version
+    /// [AUTH_VERSION](multi_authentication::AUTH_VERSION), a [Status] byte and
+    /// the method of the next stage. Only peers using this crate understand it.
+    pub mod multi_authentication {
+        use super::*;
+
+        pub const AUTH_VERSION: u8 = 0x01;
+
+        #[derive(Debug, Clone)]
+        pub struct StageStatus {
+            pub status:     Status,
+            /// The method of the next stage, or
+            /// [AuthenticationMethod::NO_ACCEPTABLE_METHODS] if there is none.
+            pub next_stage: AuthenticationMethod,
+        }
+
+        impl StageStatus {
+            pub const FAILURE: StageStatus = StageStatus {
+                status:     Status::FAILURE,
+                next_stage: AuthenticationMethod::NO_ACCEPTABLE_METHODS,
+            };
+
+            pub const fn next(next_stage: AuthenticationMethod) -> Self {
+                Self {
+                    status: Status::SUCCESS,
+                    next_stage,
+                }
+            }
+
+            /// Returns the method of the next stage, if any.
+            pub fn next_stage(&self) -> Option<AuthenticationMethod> {
+                (self.next_stage != AuthenticationMethod::NO_ACCEPTABLE_METHODS)
+                    .then_some(self.next_stage)
+            }
+        }
+
+        impl Encoder<ConversionError> for StageStatus {
+            async fn write_to<W: AsyncWrite + Unpin>(
+                &self,
+                writer: &mut W,
+            ) -> Result<(), ConversionError> {
+                writer
+                    .write_all(&[AUTH_VERSION, self.status.0, self.next_stage.into()])
+                    .await?;
+                Ok(())
+            }
+        }
+
+        impl Decoder<ConversionError> for StageStatus {
+            async fn read_from<R: AsyncRead + Unpin>(
+                reader: &mut R,
+            ) -> Result<Self, ConversionError> {
+                let mut message = [0_u8; 3];
+                reader.read_exact(&mut message).await?;
+                if message[0] != AUTH_VERSION {
+                    return Err(ConversionError::InvalidProtocolVersion(message[0]));
+                }
+                Ok(Self {
+                    status:     Status(message[1]),
+                    next_stage: AuthenticationMethod::from(message[2]),
+                })
+            }
+        }
+    }
+
+    /// Messages of the [AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK]
+    /// sub-negotiation.
+    ///
+    /// The client sends a UTF-8 JSON object prefixed with its length, and the
+    /// server replies with a status.
+    ///
+    /// The method is only registered with IANA, no specification describes its
+    /// framing, so this crate uses its own: a
+    /// [ParameterBlock](json_parameter_block::ParameterBlock) is the version
+    /// [AUTH_VERSION](json_parameter_block::AUTH_VERSION), the length of the
+    /// object on two bytes in network order and the object, and the reply is
+    /// the version and a [Status] byte. Only peers using this crate understand
+    /// it.
+    pub mod json_parameter_block {
+        use super::*;
+
+        pub const AUTH_VERSION: u8 = 0x01;
+
+        #[derive(Debug, Clone)]
+        pub struct ParameterBlock {
+            /// The JSON text of the parameters.
+            pub parameters: Box<[u8]>,
+        }
+
+        impl Encoder<ConversionError> for ParameterBlock {
+            async fn write_to<W: AsyncWrite + Unpin>(
+                &self,
+                writer: &mut W,
+            ) -> Result<(), ConversionError> {
+                let Ok(length) = u16::try_from(self.parameters.len()) else {
+                    return Err(ConversionError::MalformedMessage);
+                };
+
+                let mut message = Vec::with_capacity(self.parameters.len().saturating_add(3));
+                message.push(AUTH_VERSION);
+                message.extend_from_slice(&length.to_be_bytes());
+                message.extend_from_slice(&self.parameters);
+                writer.write_all(&message).await?;
+                Ok(())
+            }
+        }
+
+        impl Decoder<ConversionError> for ParameterBlock {
+            async fn read_from<R: AsyncRead + Unpin>(
+                reader: &mut R,
+            ) -> Result<Self, ConversionError> {
+                let mut header = [0_u8; 3];
+                reader.read_exact(&mut header).await?;
+                if header[0] != AUTH_VERSION {
+                    return Err(ConversionError::InvalidProtocolVersion(header[0]));
+                }
+
+                let mut parameters =
+                    vec![0_u8; usize::from(u16::from_be_bytes([header[1], header[2]]))];
+                reader.read_exact(&mut parameters).await?;
+
+                Ok(Self {
+                    parameters: parameters.into_boxed_slice(),
+                })
+            }
+        }
+
+        #[derive(Debug, Clone)]
+        pub struct ServerResponse {
+            pub status: Status,
+        }
+
+        impl ServerResponse {
+            pub const FAILURE: ServerResponse = ServerResponse {
+                status: Status::FAILURE,
+            };
+            pub const SUCCESS: ServerResponse = ServerResponse {
+                status: Status::SUCCESS,
+            };
+        }
+
+        impl Encoder<ConversionError> for ServerResponse {
+            async fn write_to<W: AsyncWrite + Unpin>(
+                &self,
+                writer: &mut W,
+            ) -> Result<(), ConversionError> {
+                writer.write_all(&[AUTH_VERSION, self.status.0]).await?;
+                Ok(())
+            }
+        }
+
+        impl Decoder<ConversionError> for ServerResponse {
+            async fn read_from<R: AsyncRead + Unpin>(
+                reader: &mut R,
+            ) -> Result<Self, ConversionError> {
+                let mut message = [0_u8; 2];
+                reader.read_exact(&mut message).await?;
+                if message[0] != AUTH_VERSION {
+                    return Err(ConversionError::InvalidProtocolVersion(message[0]));
+                }
+                Ok(Self {
+                    status: Status(message[1]),
+                })
+            }
+        }
+    }
 }
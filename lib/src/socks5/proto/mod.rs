@@ -1,4 +1,5 @@
 pub mod messages;
+pub mod reassembly;
 
 use std::fmt::Debug;
 
@@ -120,6 +121,15 @@ impl From<std::net::Ipv6Addr> for Address {
     }
 }
 
+impl From<std::net::IpAddr> for Address {
+    fn from(value: std::net::IpAddr) -> Self {
+        match value {
+            | std::net::IpAddr::V4(addr) => Address::Ipv4(addr),
+            | std::net::IpAddr::V6(addr) => Address::Ipv6(addr),
+        }
+    }
+}
+
 impl From<String> for Address {
     fn from(value: String) -> Self {
         Address::Domain(value.into_bytes().into_boxed_slice())
@@ -151,12 +161,83 @@ impl From<&[u8]> for Address {
     }
 }
 
+impl Address {
+    /// Appends the wire representation of the address to the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConversionError::MalformedMessage] if the domain name is
+    /// longer than 255 bytes.
+    pub fn encode_into(&self, buffer: &mut Vec<u8>) -> Result<(), ConversionError> {
+        match *self {
+            | Address::Ipv4(addr) => {
+                buffer.push(AddressType::IP_V4.0);
+                buffer.extend_from_slice(&addr.octets());
+            },
+            | Address::Domain(ref domain) => {
+                let Ok(length) = u8::try_from(domain.len()) else {
+                    return Err(ConversionError::MalformedMessage);
+                };
+                buffer.extend_from_slice(&[AddressType::DOMAIN_NAME.0, length]);
+                buffer.extend_from_slice(domain);
+            },
+            | Address::Ipv6(addr) => {
+                buffer.push(AddressType::IP_V6.0);
+                buffer.extend_from_slice(&addr.octets());
+            },
+        }
+        Ok(())
+    }
+
+    /// Parses an address from the beginning of the buffer.
+    ///
+    /// Returns the address alongside with the rest of the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ConversionError::MalformedMessage] if the buffer is too short
+    /// or the address type is unknown.
+    pub fn parse(buffer: &[u8]) -> Result<(Self, &[u8]), ConversionError> {
+        let (&address_type, rest) = buffer
+            .split_first()
+            .ok_or(ConversionError::MalformedMessage)?;
+        match address_type.into() {
+            | AddressType::IP_V4 => {
+                let (octets, rest) = rest
+                    .split_first_chunk::<4>()
+                    .ok_or(ConversionError::MalformedMessage)?;
+                Ok((Address::Ipv4((*octets).into()), rest))
+            },
+            | AddressType::DOMAIN_NAME => {
+                let (&length, rest) = rest
+                    .split_first()
+                    .ok_or(ConversionError::MalformedMessage)?;
+                let (domain, rest) = rest
+                    .split_at_checked(usize::from(length))
+                    .ok_or(ConversionError::MalformedMessage)?;
+                Ok((Address::from(domain), rest))
+            },
+            | AddressType::IP_V6 => {
+                let (octets, rest) = rest
+                    .split_first_chunk::<16>()
+                    .ok_or(ConversionError::MalformedMessage)?;
+                Ok((Address::Ipv6((*octets).into()), rest))
+            },
+            | _ => Err(ConversionError::MalformedMessage),
+        }
+    }
+}
+
 impl Encoder<ConversionError> for Address {
     async fn write_to<W: futures::AsyncWrite + Unpin>(
         &self,
         writer: &mut W,
     ) -> Result<(), ConversionError> {
-        use Address::*;
+        use Address::{
+            Domain,
+            Ipv4,
+            Ipv6,
+        };
 
         match self {
             | Ipv4(addr) => {
@@ -164,9 +245,11 @@ impl Encoder<ConversionError> for Address {
                 writer.write_all(&addr.octets()).await?;
             },
             | Domain(domain) => {
-                writer.write_all(&[AddressType::DOMAIN_NAME.0]).await?;
+                let Ok(length) = u8::try_from(domain.len()) else {
+                    return Err(ConversionError::MalformedMessage);
+                };
                 writer
-                    .write_all(&(domain.len() as u8).to_be_bytes())
+                    .write_all(&[AddressType::DOMAIN_NAME.0, length])
                     .await?;
                 writer.write_all(domain).await?;
             },
@@ -215,6 +298,9 @@ caret_int! {
         CONNECT = 0x01,
         BIND = 0x02,
         UDP_ASSOCIATE = 0x03,
+        // Tor extensions: https://spec.torproject.org/socks-extensions.html
+        RESOLVE = 0xF0,
+        RESOLVE_PTR = 0xF1,
     }
 }
 
@@ -0,0 +1,164 @@
+//! Reassembly of fragmented UDP datagrams.
+//!
+//! [RFC 1928, section 7](https://www.rfc-editor.org/rfc/rfc1928#section-7)
+//! lets a client split a datagram into up to 127 fragments numbered by the
+//! `FRAG` field of the [UdpHeader], with the high-order bit marking the last
+//! fragment. Fragments must arrive in increasing order: a position lower than
+//! or equal to the highest one processed restarts the queue.
+
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use super::{
+    Address,
+    messages::{
+        UdpDatagram,
+        UdpHeader,
+    },
+};
+
+/// The bit of the `FRAG` field that marks the last fragment of a datagram.
+pub const END_OF_SEQUENCE: u8 = 0x80;
+
+/// Limits applied to a [Reassembler].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// How long an incomplete queue is kept after its first fragment arrived.
+    pub timeout:        Duration,
+    /// The maximum amount of payload bytes an incomplete queue may hold.
+    pub max_queue_size: usize,
+}
+
+impl Default for Config {
+    /// The RFC asks for a timer of no less than 5 seconds; the size limit
+    /// matches the largest datagram that can be relayed.
+    fn default() -> Self {
+        Self {
+            timeout:        Duration::from_secs(5),
+            max_queue_size: usize::from(u16::MAX),
+        }
+    }
+}
+
+/// A reassembly queue with its timer, meant to be kept per association.
+#[derive(Debug)]
+pub struct Reassembler {
+    config: Config,
+    queue:  Option<Queue>,
+}
+
+#[derive(Debug)]
+struct Queue {
+    address:  Address,
+    port:     u16,
+    started:  Instant,
+    position: u8,
+    data:     Vec<u8>,
+    /// Whether every fragment so far has been queued.
+    intact:   bool,
+}
+
+impl Reassembler {
+    pub const fn new(config: Config) -> Self {
+        Self {
+            config,
+            queue: None,
+        }
+    }
+
+    /// Processes a received datagram.
+    ///
+    /// Returns the complete datagram once its last fragment is processed.
+    /// Unfragmented datagrams are returned as is and leave the queue
+    /// untouched. A fragment whose position isn't higher than the last one
+    /// processed, or whose destination differs, abandons the queue and starts
+    /// a new one. A queue missing a fragment, e.g. one that was lost or
+    /// exceeded the size limit, is never completed.
+    pub fn push(&mut self, header: &UdpHeader, payload: &[u8]) -> Option<UdpDatagram> {
+        if header.fragment == 0 {
+            return Some(UdpDatagram {
+                header: header.clone(),
+                data:   payload.into(),
+            });
+        }
+
+        self.expire();
+
+        let position = header.fragment & !END_OF_SEQUENCE;
+        let is_last = header.fragment & END_OF_SEQUENCE != 0;
+
+        if position == 0 {
+            return None;
+        }
+
+        let mut queue = match self.queue.take() {
+            | Some(queue)
+                if position > queue.position
+                    && queue.address == header.address
+                    && queue.port == header.port =>
+            {
+                queue
+            },
+            | _ => {
+                Queue {
+                    address:  header.address.clone(),
+                    port:     header.port,
+                    started:  Instant::now(),
+                    position: 0,
+                    data:     Vec::new(),
+                    intact:   true,
+                }
+            },
+        };
+
+        if position != queue.position.saturating_add(1)
+            || queue.data.len().saturating_add(payload.len()) > self.config.max_queue_size
+        {
+            queue.intact = false;
+            queue.data = Vec::new();
+        }
+        if queue.intact {
+            queue.data.extend_from_slice(payload);
+        }
+        queue.position = position;
+
+        if is_last {
+            if !queue.intact {
+                return None;
+            }
+            return Some(UdpDatagram {
+                header: UdpHeader {
+                    fragment: 0,
+                    address:  queue.address,
+                    port:     queue.port,
+                },
+                data:   queue.data.into_boxed_slice(),
+            });
+        }
+
+        self.queue = Some(queue);
+        None
+    }
+
+    /// Drops the incomplete queue if its timer has expired.
+    pub fn expire(&mut self) {
+        if self
+            .queue
+            .as_ref()
+            .is_some_and(|queue| queue.started.elapsed() >= self.config.timeout)
+        {
+            self.queue = None;
+        }
+    }
+
+    /// Returns the moment the incomplete queue expires, if there's one.
+    ///
+    /// The queue is only checked when a fragment arrives, so a timer should
+    /// call [Reassembler::expire] at that moment to release it on time.
+    pub fn deadline(&self) -> Option<Instant> {
+        let queue = self.queue.as_ref()?;
+        queue.started.checked_add(self.config.timeout)
+    }
+}
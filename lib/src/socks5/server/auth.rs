@@ -0,0 +1,1002 @@
+//! Pluggable authentication methods.
+//!
+//! Each [Authenticator] performs the sub-negotiation of a single
+//! [AuthenticationMethod], including the ones from the private-use range
+//! (`0x80`-`0xFE`), and yields the [Identity] of the client. Servers keep
+//! them in an [Authenticators] registry.
+
+use std::sync;
+#[cfg(feature = "tokio")]
+use std::{
+    ffi::OsString,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    process::Stdio,
+    time::Duration,
+};
+
+use futures::{
+    AsyncRead,
+    AsyncWrite,
+    AsyncWriteExt as _,
+    future::BoxFuture,
+};
+
+use super::{
+    BoxedStream,
+    ConnectionContext,
+    PeerCredentials,
+    ServerError,
+    credentials::{
+        CredentialStore,
+        SecretStore,
+    },
+};
+#[cfg(feature = "jwt")]
+use crate::socks5::jwt::{
+    Claims,
+    TokenVerifier,
+};
+#[cfg(feature = "json")]
+use crate::socks5::proto::messages::auth::json_parameter_block;
+#[cfg(feature = "tls")]
+use crate::socks5::tls::Acceptor;
+use crate::{
+    codec::{
+        Decoder as _,
+        Encoder as _,
+    },
+    socks5::{
+        chap::{
+            DEFAULT_ALGORITHMS,
+            challenge,
+            verify,
+        },
+        gssapi::{
+            Mechanism,
+            Session,
+        },
+        proto::{
+            AuthenticationMethod,
+            Status,
+            messages::auth::{
+                chap::{
+                    self,
+                    Algorithm,
+                    Attribute,
+                    AttributeType,
+                },
+                gssapi::{
+                    Message,
+                    MessageType,
+                    ProtectionLevel,
+                },
+                multi_authentication::StageStatus,
+                username_password,
+            },
+        },
+    },
+};
+
+/// Who the client is, as established by an [Authenticator].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Identity {
+    /// The client did not authenticate.
+    Anonymous,
+
+    /// The client authenticated as the given user.
+    User(Box<[u8]>),
+
+    /// The client authenticated as the given GSS-API principal.
+    Principal(Box<[u8]>),
+
+    /// The client went through several stages, yielding these identities in
+    /// order.
+    Multiple(Box<[Identity]>),
+
+    /// The client presented a TLS certificate, mapped to the given name.
+    Certificate(Box<str>),
+
+    /// The client is a local process with the given credentials.
+    Peer(PeerCredentials),
+
+    /// The client presented a signed token with the given claims.
+    #[cfg(feature = "jwt")]
+    Token(Claims),
+}
+
+/// The server side of an authentication method.
+pub trait Authenticator<S>: Send + Sync {
+    /// Returns the method this authenticator handles.
+    fn method(&self) -> AuthenticationMethod;
+
+    /// Performs the sub-negotiation of the method over the stream.
+    ///
+    /// The context tells what is known about the connection so far, e.g. the
+    /// client's address, and keeps what the method learns about the client
+    /// besides its identity. Implementations are expected to close the stream
+    /// after sending a failure status.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ServerError::AuthenticationFailed] if the client is rejected,
+    /// or another [ServerError] if the exchange fails.
+    fn authenticate<'auth>(
+        &'auth self,
+        stream: &'auth mut S,
+        context: &'auth mut ConnectionContext,
+    ) -> BoxFuture<'auth, Result<Identity, ServerError>>;
+}
+
+/// A set of [Authenticator]s, at most one per method.
+pub struct Authenticators<S> {
+    entries: Vec<sync::Arc<dyn Authenticator<S>>>,
+}
+
+impl<S> Authenticators<S> {
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Adds an authenticator, replacing the one registered for the same
+    /// method.
+    ///
+    /// Authenticators for [AuthenticationMethod::NO_ACCEPTABLE_METHODS] are
+    /// ignored, as the method can't be selected.
+    pub fn register<A: Authenticator<S> + 'static>(&mut self, authenticator: A) {
+        let method = authenticator.method();
+        if method == AuthenticationMethod::NO_ACCEPTABLE_METHODS {
+            return;
+        }
+
+        let authenticator = sync::Arc::new(authenticator);
+        match self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.method() == method)
+        {
+            | Some(entry) => *entry = authenticator,
+            | None => self.entries.push(authenticator),
+        }
+    }
+
+    /// Returns the authenticator registered for the method.
+    pub fn get(&self, method: AuthenticationMethod) -> Option<&dyn Authenticator<S>> {
+        self.entries
+            .iter()
+            .find(|entry| entry.method() == method)
+            .map(|entry| &**entry)
+    }
+
+    /// Returns the registered methods, in the order of registration.
+    pub fn methods(&self) -> Box<[AuthenticationMethod]> {
+        self.entries.iter().map(|entry| entry.method()).collect()
+    }
+}
+
+impl<S> Default for Authenticators<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Clone for Authenticators<S> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+/// Accepts everyone as [Identity::Anonymous] with
+/// [AuthenticationMethod::NO_AUTHENTICATION].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoAuthentication;
+
+impl<S: Send> Authenticator<S> for NoAuthentication {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::NO_AUTHENTICATION
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        _: &'a mut S,
+        _: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async { Ok(Identity::Anonymous) })
+    }
+}
+
+/// A check of the credentials received by [LocalPeer].
+type PeerFilter = dyn Fn(&PeerCredentials) -> bool + Send + Sync;
+
+/// Authorizes local processes by the credentials of their end of a Unix
+/// domain socket, with [AuthenticationMethod::NO_AUTHENTICATION].
+///
+/// The credentials are taken from [ConnectionContext::peer_credentials],
+/// which is set by listeners bound with
+/// [Socks5Listener::bind_unix](crate::socks5::server::tokio::Socks5Listener::bind_unix).
+/// Accepted clients are identified as an [Identity::Peer]. Clients without
+/// credentials, e.g. over TCP, are rejected. As the method has no
+/// sub-negotiation, rejected clients are disconnected without a status.
+#[derive(Clone)]
+pub struct LocalPeer {
+    filter: sync::Arc<PeerFilter>,
+}
+
+impl LocalPeer {
+    /// Accepts the processes the filter returns `true` for.
+    pub fn new<F>(filter: F) -> Self
+    where
+        F: Fn(&PeerCredentials) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            filter: sync::Arc::new(filter),
+        }
+    }
+
+    /// Accepts the processes running as one of the users.
+    pub fn users<U: Into<Box<[u32]>>>(user_ids: U) -> Self {
+        let user_ids = user_ids.into();
+        Self::new(move |credentials| user_ids.contains(&credentials.user_id))
+    }
+}
+
+impl<S: AsyncWrite + Unpin + Send> Authenticator<S> for LocalPeer {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::NO_AUTHENTICATION
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut S,
+        context: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            match context.peer_credentials {
+                | Some(credentials) if (self.filter)(&credentials) => {
+                    Ok(Identity::Peer(credentials))
+                },
+                | _ => {
+                    stream.close().await?;
+                    Err(ServerError::AuthenticationFailed)
+                },
+            }
+        })
+    }
+}
+
+/// Checks [AuthenticationMethod::USERNAME_PASSWORD] credentials against a
+/// [CredentialStore].
+///
+/// Within a Tokio runtime, the store is queried on the blocking thread pool,
+/// as password hashes are slow to check by design.
+#[derive(Clone)]
+pub struct UsernamePassword {
+    store: sync::Arc<dyn CredentialStore>,
+}
+
+impl UsernamePassword {
+    pub fn new<C: CredentialStore + 'static>(store: C) -> Self {
+        Self {
+            store: sync::Arc::new(store),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Authenticator<S> for UsernamePassword {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::USERNAME_PASSWORD
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut S,
+        _: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            let request = read_username_password(stream).await?;
+            let accepted =
+                verify_credentials(&self.store, &request.username, request.password).await;
+            send_username_password_status(stream, accepted).await?;
+            Ok(Identity::User(request.username))
+        })
+    }
+}
+
+/// Queries the store, off the async executor when running within a Tokio
+/// runtime. A panicking store rejects the client.
+async fn verify_credentials(
+    store: &sync::Arc<dyn CredentialStore>,
+    username: &[u8],
+    password: Box<[u8]>,
+) -> bool {
+    #[cfg(feature = "tokio")]
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        let store = sync::Arc::clone(store);
+        let username = Box::<[u8]>::from(username);
+        return handle
+            .spawn_blocking(move || store.verify(&username, &password))
+            .await
+            .unwrap_or(false);
+    }
+
+    store.verify(username, &password)
+}
+
+/// Checks signed tokens sent as the password of the
+/// [AuthenticationMethod::USERNAME_PASSWORD] method with a [TokenVerifier].
+///
+/// The username is not checked, as clients are identified by the claims of
+/// their token: accepted clients are an [Identity::Token].
+#[cfg(feature = "jwt")]
+#[derive(Clone)]
+pub struct JsonWebToken {
+    verifier: sync::Arc<TokenVerifier>,
+}
+
+#[cfg(feature = "jwt")]
+impl JsonWebToken {
+    pub fn new(verifier: TokenVerifier) -> Self {
+        Self {
+            verifier: sync::Arc::new(verifier),
+        }
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Authenticator<S> for JsonWebToken {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::USERNAME_PASSWORD
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut S,
+        _: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            let request = read_username_password(stream).await?;
+            let claims = self.verifier.verify(&request.password);
+            send_username_password_status(stream, claims.is_some()).await?;
+            claims
+                .map(Identity::Token)
+                .ok_or(ServerError::AuthenticationFailed)
+        })
+    }
+}
+
+/// Checks [AuthenticationMethod::USERNAME_PASSWORD] credentials by running an
+/// external command, e.g. an existing authentication script.
+///
+/// The command gets the username and the password on its standard input,
+/// each followed by a newline, and the `SOCKS_USERNAME` and
+/// `SOCKS_PEER_ADDR` environment variables, the latter being empty if the
+/// client has no IP address. The rest of the server's environment is
+/// inherited, unless restricted with [ExternalCommand::with_inherited_env].
+/// The credentials are accepted if the command exits successfully. Its
+/// standard output is discarded, its standard error is inherited.
+///
+/// Credentials containing a newline, commands that can't be started and
+/// commands that don't finish in time are rejected. The timeout includes
+/// waiting for one of the limited number of concurrent commands to finish.
+#[cfg(feature = "tokio")]
+#[derive(Clone)]
+pub struct ExternalCommand {
+    program:       OsString,
+    args:          Box<[OsString]>,
+    inherited_env: Option<Box<[OsString]>>,
+    timeout:       Duration,
+    permits:       sync::Arc<tokio::sync::Semaphore>,
+}
+
+#[cfg(feature = "tokio")]
+impl ExternalCommand {
+    /// Creates an authenticator running the program, with a timeout of five
+    /// seconds and at most eight concurrent commands.
+    pub fn new<P: Into<OsString>>(program: P) -> Self {
+        Self {
+            program:       program.into(),
+            args:          Box::default(),
+            inherited_env: None,
+            timeout:       Duration::from_secs(5),
+            permits:       sync::Arc::new(tokio::sync::Semaphore::new(8)),
+        }
+    }
+
+    /// Sets the arguments of the program.
+    pub fn with_args<I: IntoIterator<Item = A>, A: Into<OsString>>(mut self, args: I) -> Self {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only passes the given variables of the server's environment to the
+    /// command, instead of the whole environment. An empty list passes none.
+    pub fn with_inherited_env<I: IntoIterator<Item = V>, V: Into<OsString>>(
+        mut self,
+        names: I,
+    ) -> Self {
+        self.inherited_env = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets how long a command may take before the credentials are rejected.
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many commands may run at the same time.
+    pub fn with_concurrency_limit(mut self, limit: NonZeroUsize) -> Self {
+        self.permits = sync::Arc::new(tokio::sync::Semaphore::new(limit.get()));
+        self
+    }
+
+    /// Runs the command and returns whether it accepts the credentials.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the command can't be started or fed.
+    async fn run(
+        &self,
+        username: &[u8],
+        password: &[u8],
+        peer_addr: Option<SocketAddr>,
+    ) -> std::io::Result<bool> {
+        use tokio::io::AsyncWriteExt as _;
+
+        let Ok(_permit) = self.permits.acquire().await else {
+            return Ok(false);
+        };
+
+        let mut command = tokio::process::Command::new(&self.program);
+        if let Some(names) = self.inherited_env.as_deref() {
+            command.env_clear();
+            for name in names {
+                if let Some(value) = std::env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
+        }
+
+        let mut child = command
+            .args(&self.args)
+            .env("SOCKS_USERNAME", String::from_utf8_lossy(username).as_ref())
+            .env(
+                "SOCKS_PEER_ADDR",
+                peer_addr.map(|addr| addr.to_string()).unwrap_or_default(),
+            )
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            let input = [username, b"\n", password, b"\n"].concat();
+            // The command may exit without reading its input.
+            let _ = stdin.write_all(&input).await;
+        }
+
+        Ok(child.wait().await?.success())
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Authenticator<S> for ExternalCommand {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::USERNAME_PASSWORD
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut S,
+        context: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            let request = read_username_password(stream).await?;
+            let valid = !request.username.contains(&b'\n') && !request.password.contains(&b'\n');
+            let accepted = valid
+                && matches!(
+                    tokio::time::timeout(
+                        self.timeout,
+                        self.run(&request.username, &request.password, context.peer_addr),
+                    )
+                    .await,
+                    Ok(Ok(true))
+                );
+            send_username_password_status(stream, accepted).await?;
+            Ok(Identity::User(request.username))
+        })
+    }
+}
+
+/// Checks [AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE] responses against
+/// a [SecretStore].
+///
+/// The client answers a random challenge with its HMAC keyed with the
+/// password, so the password is never sent. A single challenge is issued.
+#[derive(Clone)]
+pub struct ChallengeHandshake {
+    store:      sync::Arc<dyn SecretStore>,
+    algorithms: Box<[Algorithm]>,
+}
+
+impl ChallengeHandshake {
+    pub fn new<C: SecretStore + 'static>(store: C) -> Self {
+        Self {
+            store:      sync::Arc::new(store),
+            algorithms: DEFAULT_ALGORITHMS.into(),
+        }
+    }
+
+    /// Sets the accepted algorithms, in the order of preference.
+    ///
+    /// By default, only [Algorithm::HMAC_MD5] is accepted.
+    pub fn with_algorithms<A: Into<Box<[Algorithm]>>>(mut self, algorithms: A) -> Self {
+        self.algorithms = algorithms.into();
+        self
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Authenticator<S> for ChallengeHandshake {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut S,
+        _: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            let offer = chap::Message::read_from(stream).await?;
+            let offered = offer.get(AttributeType::ALGORITHMS).unwrap_or_default();
+            let Some(algorithm) = self
+                .algorithms
+                .iter()
+                .copied()
+                .find(|algorithm| offered.contains(&(*algorithm).into()))
+            else {
+                return reject_challenge_handshake(stream).await;
+            };
+
+            let challenge = challenge()?;
+            chap::Message::new([
+                Attribute::new(AttributeType::ALGORITHMS, [algorithm.into()]),
+                Attribute::new(AttributeType::CHALLENGE, challenge),
+            ])
+            .write_to(stream)
+            .await?;
+
+            let answer = chap::Message::read_from(stream).await?;
+            let (Some(username), Some(response)) = (
+                answer.get(AttributeType::USER_IDENTITY),
+                answer.get(AttributeType::RESPONSE),
+            ) else {
+                return reject_challenge_handshake(stream).await;
+            };
+
+            // Unknown users are checked against an empty secret, so that they
+            // cost as much as known ones.
+            let secret = self.store.secret(username);
+            let matches = verify(
+                algorithm,
+                secret.as_deref().unwrap_or_default(),
+                &challenge,
+                response,
+            );
+            if !(matches && secret.is_some()) {
+                return reject_challenge_handshake(stream).await;
+            }
+
+            chap::Message::status(Status::SUCCESS)
+                .write_to(stream)
+                .await?;
+            Ok(Identity::User(username.into()))
+        })
+    }
+}
+
+/// A check of the parameters received by [JsonParameters].
+#[cfg(feature = "json")]
+type ParametersValidator =
+    dyn Fn(&serde_json::Map<String, serde_json::Value>) -> bool + Send + Sync;
+
+/// Receives the [AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK] parameters,
+/// e.g. a tenant, session tags or routing hints.
+///
+/// The parameters must be a JSON object. They are recorded in
+/// [ConnectionContext::parameters], and the client stays
+/// [Identity::Anonymous], as the parameters are not credentials.
+#[cfg(feature = "json")]
+#[derive(Clone, Default)]
+pub struct JsonParameters {
+    validator: Option<sync::Arc<ParametersValidator>>,
+}
+
+#[cfg(feature = "json")]
+impl JsonParameters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only accepts the parameters the validator returns `true` for.
+    pub fn with_validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&serde_json::Map<String, serde_json::Value>) -> bool + Send + Sync + 'static,
+    {
+        self.validator = Some(sync::Arc::new(validator));
+        self
+    }
+}
+
+#[cfg(feature = "json")]
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Authenticator<S> for JsonParameters {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut S,
+        context: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            let block = json_parameter_block::ParameterBlock::read_from(stream).await?;
+            let parameters = serde_json::from_slice::<serde_json::Value>(&block.parameters)
+                .ok()
+                .and_then(|parameters| {
+                    match parameters {
+                        | serde_json::Value::Object(parameters) => Some(parameters),
+                        | _ => None,
+                    }
+                })
+                .filter(|parameters| {
+                    self.validator
+                        .as_ref()
+                        .is_none_or(|validator| validator(parameters))
+                });
+
+            let Some(parameters) = parameters else {
+                json_parameter_block::ServerResponse::FAILURE
+                    .write_to(stream)
+                    .await?;
+                stream.close().await?;
+                return Err(ServerError::AuthenticationFailed);
+            };
+
+            json_parameter_block::ServerResponse::SUCCESS
+                .write_to(stream)
+                .await?;
+            context.parameters = Some(parameters);
+            Ok(Identity::Anonymous)
+        })
+    }
+}
+
+/// Upgrades the session to TLS with the
+/// [AuthenticationMethod::IANA_SECURE_SOCKETS_LAYER] method, then checks
+/// [AuthenticationMethod::USERNAME_PASSWORD] credentials inside the tunnel.
+///
+/// The TLS handshake starts right after the method is chosen, and the rest of
+/// the session is encrypted. The client then sends its username and password
+/// as in RFC 1929, which are checked against a [CredentialStore]. Client
+/// certificates, if the acceptor requires them, only gate the handshake.
+#[cfg(feature = "tls")]
+#[derive(Clone)]
+pub struct TlsUpgrade {
+    acceptor: Acceptor,
+    store:    sync::Arc<dyn CredentialStore>,
+    timeout:  Duration,
+}
+
+#[cfg(feature = "tls")]
+impl TlsUpgrade {
+    pub fn new<C: CredentialStore + 'static>(acceptor: Acceptor, store: C) -> Self {
+        Self {
+            acceptor,
+            store: sync::Arc::new(store),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Sets how long clients have to complete the TLS handshake before being
+    /// disconnected.
+    ///
+    /// By default, they have 10 seconds, as with
+    /// [Socks5Listener::with_tls_timeout](super::tokio::Socks5Listener::with_tls_timeout).
+    pub const fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Authenticator<BoxedStream> for TlsUpgrade {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::IANA_SECURE_SOCKETS_LAYER
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut BoxedStream,
+        _: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        use tokio_util::compat::{
+            FuturesAsyncReadCompatExt as _,
+            TokioAsyncReadCompatExt as _,
+        };
+
+        Box::pin(async move {
+            let inner = stream.take();
+            let tls_stream =
+                tokio::time::timeout(self.timeout, self.acceptor.accept(inner.compat()))
+                    .await
+                    .map_err(std::io::Error::from)??;
+            *stream = BoxedStream::new(tls_stream.compat());
+
+            let request = read_username_password(stream).await?;
+            let accepted =
+                verify_credentials(&self.store, &request.username, request.password).await;
+            send_username_password_status(stream, accepted).await?;
+            Ok(Identity::User(request.username))
+        })
+    }
+}
+
+/// Performs the [AuthenticationMethod::GSSAPI] sub-negotiation with a
+/// [Mechanism].
+///
+/// After the security context is established and the protection level is
+/// negotiated, the stream is replaced by an
+/// [EncapsulatedStream](crate::socks5::gssapi::EncapsulatedStream), so the
+/// rest of the session is protected. The client is identified as an
+/// [Identity::Principal].
+#[derive(Clone)]
+pub struct Gssapi {
+    mechanism: sync::Arc<dyn Mechanism>,
+    levels:    Box<[ProtectionLevel]>,
+}
+
+impl Gssapi {
+    pub fn new<M: Mechanism + 'static>(mechanism: M) -> Self {
+        Self {
+            mechanism: sync::Arc::new(mechanism),
+            levels:    [
+                ProtectionLevel::CONFIDENTIALITY,
+                ProtectionLevel::SELECTIVE,
+                ProtectionLevel::INTEGRITY,
+            ]
+            .into(),
+        }
+    }
+
+    /// Sets the accepted protection levels, in the order of preference.
+    ///
+    /// The level requested by the client is used if it's accepted, otherwise
+    /// the preferred one is offered instead.
+    pub fn with_protection_levels<L: Into<Box<[ProtectionLevel]>>>(mut self, levels: L) -> Self {
+        self.levels = levels.into();
+        self
+    }
+
+    /// Establishes the security context and negotiates the protection level.
+    ///
+    /// # Errors
+    ///
+    /// Returns [ServerError::AuthenticationFailed] if the client is rejected,
+    /// or another [ServerError] if the exchange fails.
+    async fn negotiate(&self, stream: &mut BoxedStream) -> Result<Session, ServerError> {
+        let mut context = self.mechanism.accept().map_err(ServerError::from)?;
+
+        while !context.is_established() {
+            let message = Message::read_from(stream).await?;
+            if message.message_type != MessageType::AUTHENTICATION {
+                return Err(ServerError::AuthenticationFailed);
+            }
+
+            let output = context
+                .step(Some(&message.token))
+                .map_err(ServerError::from)?;
+            if let Some(token) = output {
+                Message::new(MessageType::AUTHENTICATION, token)
+                    .write_to(stream)
+                    .await?;
+            }
+        }
+
+        let message = Message::read_from(stream).await?;
+        if message.message_type != MessageType::PROTECTION_LEVEL {
+            return Err(ServerError::AuthenticationFailed);
+        }
+        let requested = match *context.unwrap(&message.token).map_err(ServerError::from)? {
+            | [level] => ProtectionLevel::from(level),
+            | _ => return Err(ServerError::AuthenticationFailed),
+        };
+
+        let level = if self.levels.contains(&requested) {
+            requested
+        } else {
+            *self
+                .levels
+                .first()
+                .ok_or(ServerError::AuthenticationFailed)?
+        };
+        let token = context
+            .wrap(&[level.into()], false)
+            .map_err(ServerError::from)?;
+        Message::new(MessageType::PROTECTION_LEVEL, token)
+            .write_to(stream)
+            .await?;
+
+        Ok(Session::new(context, level))
+    }
+}
+
+impl Authenticator<BoxedStream> for Gssapi {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::GSSAPI
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut BoxedStream,
+        _: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            let session = match self.negotiate(stream).await {
+                | Ok(session) => session,
+                | Err(error) => {
+                    if !matches!(error, ServerError::IoError(_)) {
+                        Message::abort().write_to(stream).await?;
+                        stream.close().await?;
+                    }
+                    return Err(error);
+                },
+            };
+
+            let identity = Identity::Principal(session.peer_name().unwrap_or_default());
+            stream.replace_with(|inner| session.encapsulate(inner));
+            Ok(identity)
+        })
+    }
+}
+
+/// Performs the [AuthenticationMethod::IANA_MULTI_AUTHENTICATION_FRAMEWORK]
+/// sub-negotiation, requiring the client to pass every stage in order.
+///
+/// The client is identified as an [Identity::Multiple] holding the identity
+/// of every stage. If a stage rejects the client, a failure status is sent
+/// after the stage's own reply, if any, unless the connection is lost.
+pub struct MultiAuthentication<S> {
+    stages: Vec<sync::Arc<dyn Authenticator<S>>>,
+}
+
+impl<S> MultiAuthentication<S> {
+    pub const fn new() -> Self {
+        Self {
+            stages: Vec::new()
+        }
+    }
+
+    /// Appends a stage.
+    pub fn then<A: Authenticator<S> + 'static>(mut self, authenticator: A) -> Self {
+        self.stages.push(sync::Arc::new(authenticator));
+        self
+    }
+
+    /// Returns the methods of the stages, in order.
+    pub fn stages(&self) -> Box<[AuthenticationMethod]> {
+        self.stages.iter().map(|stage| stage.method()).collect()
+    }
+}
+
+impl<S> Default for MultiAuthentication<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Clone for MultiAuthentication<S> {
+    fn clone(&self) -> Self {
+        Self {
+            stages: self.stages.clone(),
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin + Send> Authenticator<S> for MultiAuthentication<S> {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::IANA_MULTI_AUTHENTICATION_FRAMEWORK
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut S,
+        context: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            let mut identities = Vec::with_capacity(self.stages.len());
+            let mut stages = self.stages.iter().peekable();
+            let first = stages.peek().map(|stage| stage.method());
+            StageStatus::next(first.unwrap_or(AuthenticationMethod::NO_ACCEPTABLE_METHODS))
+                .write_to(stream)
+                .await?;
+
+            while let Some(stage) = stages.next() {
+                match stage.authenticate(stream, context).await {
+                    | Ok(identity) => identities.push(identity),
+                    | Err(error) => {
+                        if !matches!(error, ServerError::IoError(_)) {
+                            // The stage may have closed the stream already.
+                            let _ = StageStatus::FAILURE.write_to(stream).await;
+                        }
+                        return Err(error);
+                    },
+                }
+
+                let next = stages.peek().map(|next| next.method());
+                StageStatus::next(next.unwrap_or(AuthenticationMethod::NO_ACCEPTABLE_METHODS))
+                    .write_to(stream)
+                    .await?;
+            }
+
+            Ok(Identity::Multiple(identities.into()))
+        })
+    }
+}
+
+/// Reads the client's [AuthenticationMethod::USERNAME_PASSWORD] request.
+///
+/// # Errors
+///
+/// Returns a [ServerError] if the request can't be read.
+pub async fn read_username_password<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> Result<username_password::ClientAuthenticationRequest, ServerError> {
+    Ok(username_password::ClientAuthenticationRequest::read_from(stream).await?)
+}
+
+/// Replies with the [AuthenticationMethod::USERNAME_PASSWORD] sub-negotiation
+/// status. If the credentials are not accepted, the stream is closed.
+///
+/// # Errors
+///
+/// Returns [ServerError::AuthenticationFailed] if the credentials are not
+/// accepted, or another [ServerError] if the reply can't be written.
+pub async fn send_username_password_status<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    accepted: bool,
+) -> Result<(), ServerError> {
+    if !accepted {
+        username_password::ServerResponse::FAILURE
+            .write_to(stream)
+            .await?;
+        stream.close().await?;
+        return Err(ServerError::AuthenticationFailed);
+    }
+
+    username_password::ServerResponse::SUCCESS
+        .write_to(stream)
+        .await?;
+    Ok(())
+}
+
+/// Replies with a failure [AttributeType::STATUS] and closes the stream.
+///
+/// # Errors
+///
+/// Returns [ServerError::AuthenticationFailed], or another [ServerError] if
+/// the reply can't be written.
+async fn reject_challenge_handshake<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+) -> Result<Identity, ServerError> {
+    chap::Message::status(Status::FAILURE)
+        .write_to(stream)
+        .await?;
+    stream.close().await?;
+    Err(ServerError::AuthenticationFailed)
+}
@@ -0,0 +1,281 @@
+//! Credential and secret stores.
+//!
+//! Credential stores serve the
+//! [AuthenticationMethod::USERNAME_PASSWORD](crate::socks5::proto::AuthenticationMethod::USERNAME_PASSWORD)
+//! method, and secret stores the
+//! [AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE](crate::socks5::proto::AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE)
+//! one.
+//!
+//! Stores compare passwords in constant time, and checking the password of an
+//! unknown user costs as much as checking a wrong password of a known one, so
+//! that the response time doesn't reveal which users exist.
+
+#[cfg(feature = "htpasswd")]
+use std::path::Path;
+use std::{
+    collections::HashMap,
+    hash::BuildHasher,
+    sync,
+};
+
+use sha2::{
+    Digest as _,
+    Sha256,
+};
+use subtle::ConstantTimeEq as _;
+
+/// A source of username and password pairs.
+pub trait CredentialStore: Send + Sync {
+    /// Returns `true` if the password is the one of the user.
+    ///
+    /// This may block for a while, as checking a password hash does.
+    fn verify(&self, username: &[u8], password: &[u8]) -> bool;
+}
+
+impl<C: CredentialStore + ?Sized> CredentialStore for sync::Arc<C> {
+    fn verify(&self, username: &[u8], password: &[u8]) -> bool {
+        (**self).verify(username, password)
+    }
+}
+
+/// A single user.
+impl CredentialStore for (Box<[u8]>, Box<[u8]>) {
+    fn verify(&self, username: &[u8], password: &[u8]) -> bool {
+        let username_matches = digest(username).ct_eq(&digest(&self.0));
+        let password_matches = digest(password).ct_eq(&digest(&self.1));
+        (username_matches & password_matches).into()
+    }
+}
+
+/// An in-memory map of users to their passwords.
+///
+/// Only the SHA-256 digests of the passwords are kept.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    users: HashMap<Box<[u8]>, [u8; 32]>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a user, replacing the password if the user already exists.
+    pub fn insert<U: AsRef<[u8]>, P: AsRef<[u8]>>(&mut self, username: U, password: P) {
+        self.users
+            .insert(username.as_ref().into(), digest(password.as_ref()));
+    }
+
+    /// Removes a user.
+    pub fn remove(&mut self, username: &[u8]) {
+        self.users.remove(username);
+    }
+}
+
+impl<U: AsRef<[u8]>, P: AsRef<[u8]>> FromIterator<(U, P)> for MemoryStore {
+    fn from_iter<I: IntoIterator<Item = (U, P)>>(iter: I) -> Self {
+        let mut store = Self::new();
+        for (username, password) in iter {
+            store.insert(username, password);
+        }
+        store
+    }
+}
+
+impl CredentialStore for MemoryStore {
+    fn verify(&self, username: &[u8], password: &[u8]) -> bool {
+        let password = digest(password);
+        match self.users.get(username) {
+            | Some(expected) => password.ct_eq(expected).into(),
+            | None => {
+                std::hint::black_box(password.ct_eq(&[0; 32]));
+                false
+            },
+        }
+    }
+}
+
+/// A source of shared secrets.
+///
+/// Challenge-response methods need the password itself to compute the
+/// expected response, so unlike a [CredentialStore], a secret store can't
+/// keep hashes only.
+pub trait SecretStore: Send + Sync {
+    /// Returns the secret of the user, if the user exists.
+    fn secret(&self, username: &[u8]) -> Option<Box<[u8]>>;
+}
+
+impl<C: SecretStore + ?Sized> SecretStore for sync::Arc<C> {
+    fn secret(&self, username: &[u8]) -> Option<Box<[u8]>> {
+        (**self).secret(username)
+    }
+}
+
+/// A single user.
+impl SecretStore for (Box<[u8]>, Box<[u8]>) {
+    fn secret(&self, username: &[u8]) -> Option<Box<[u8]>> {
+        bool::from(digest(username).ct_eq(&digest(&self.0))).then(|| self.1.clone())
+    }
+}
+
+impl<H: BuildHasher + Send + Sync> SecretStore for HashMap<Box<[u8]>, Box<[u8]>, H> {
+    fn secret(&self, username: &[u8]) -> Option<Box<[u8]>> {
+        self.get(username).cloned()
+    }
+}
+
+/// Errors that can occur while loading an [HtpasswdStore].
+#[cfg(feature = "htpasswd")]
+#[derive(Debug)]
+pub enum HtpasswdError {
+    /// The file can't be read.
+    IoError(std::io::Error),
+
+    /// The line with the given number is not a `username:hash` pair.
+    MalformedLine(usize),
+
+    /// The hash on the line with the given number is not supported.
+    UnsupportedHash(usize),
+}
+
+#[cfg(feature = "htpasswd")]
+impl From<std::io::Error> for HtpasswdError {
+    fn from(value: std::io::Error) -> Self {
+        HtpasswdError::IoError(value)
+    }
+}
+
+/// A store backed by an htpasswd-style file.
+///
+/// Each line holds a `username:hash` pair. Empty lines and lines starting
+/// with `#` are skipped. The supported hashes are Argon2 (`$argon2id$`,
+/// `$argon2i$`, `$argon2d$`), bcrypt (`$2a$`, `$2b$`, `$2y$`) and SHA-crypt
+/// (`$5$`, `$6$`).
+///
+/// Unknown users are checked against the hash of the first user, so their
+/// cost matches the one of known users as long as all hashes share the same
+/// parameters.
+#[cfg(feature = "htpasswd")]
+#[derive(Debug, Clone, Default)]
+pub struct HtpasswdStore {
+    users: HashMap<Box<[u8]>, PasswordHash>,
+    dummy: Option<PasswordHash>,
+}
+
+#[cfg(feature = "htpasswd")]
+impl HtpasswdStore {
+    /// Reads the store from a file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [HtpasswdError] if the file can't be read or is malformed.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, HtpasswdError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads the store from the contents of a file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [HtpasswdError] if the contents are malformed.
+    pub fn parse(contents: &str) -> Result<Self, HtpasswdError> {
+        let mut store = Self::default();
+
+        for (index, line) in contents.lines().enumerate() {
+            let number = index.saturating_add(1);
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let Some((username, hash)) = line.split_once(':') else {
+                return Err(HtpasswdError::MalformedLine(number));
+            };
+            if username.is_empty() {
+                return Err(HtpasswdError::MalformedLine(number));
+            }
+            let hash = PasswordHash::parse(hash).ok_or(HtpasswdError::UnsupportedHash(number))?;
+
+            if store.dummy.is_none() {
+                store.dummy = Some(hash.clone());
+            }
+            store.users.insert(username.as_bytes().into(), hash);
+        }
+
+        Ok(store)
+    }
+}
+
+#[cfg(feature = "htpasswd")]
+impl CredentialStore for HtpasswdStore {
+    fn verify(&self, username: &[u8], password: &[u8]) -> bool {
+        match (self.users.get(username), self.dummy.as_ref()) {
+            | (Some(hash), _) => hash.verify(password),
+            | (None, Some(dummy)) => {
+                std::hint::black_box(dummy.verify(password));
+                false
+            },
+            | (None, None) => false,
+        }
+    }
+}
+
+/// A password hash in the modular crypt format.
+#[cfg(feature = "htpasswd")]
+#[derive(Debug, Clone)]
+struct PasswordHash {
+    scheme: Scheme,
+    hash:   Box<str>,
+}
+
+/// The schemes of the supported [PasswordHash]es.
+#[cfg(feature = "htpasswd")]
+#[derive(Debug, Clone, Copy)]
+enum Scheme {
+    Argon2,
+    Bcrypt,
+    Sha256Crypt,
+    Sha512Crypt,
+}
+
+#[cfg(feature = "htpasswd")]
+impl PasswordHash {
+    fn parse(hash: &str) -> Option<Self> {
+        let scheme = match hash.strip_prefix('$')?.split('$').next()? {
+            | "argon2id" | "argon2i" | "argon2d" => {
+                argon2::PasswordHash::new(hash).ok()?.hash?;
+                Scheme::Argon2
+            },
+            | "2a" | "2b" | "2y" => Scheme::Bcrypt,
+            | "5" => Scheme::Sha256Crypt,
+            | "6" => Scheme::Sha512Crypt,
+            | _ => return None,
+        };
+        Some(Self {
+            scheme,
+            hash: hash.into(),
+        })
+    }
+
+    fn verify(&self, password: &[u8]) -> bool {
+        use argon2::PasswordVerifier as _;
+
+        let hash = &*self.hash;
+        match self.scheme {
+            | Scheme::Argon2 => {
+                argon2::PasswordHash::new(hash).is_ok_and(|hash| {
+                    argon2::Argon2::default()
+                        .verify_password(password, &hash)
+                        .is_ok()
+                })
+            },
+            | Scheme::Bcrypt => pwhash::bcrypt::verify(password, hash),
+            | Scheme::Sha256Crypt => pwhash::sha256_crypt::verify(password, hash),
+            | Scheme::Sha512Crypt => pwhash::sha512_crypt::verify(password, hash),
+        }
+    }
+}
+
+fn digest(value: &[u8]) -> [u8; 32] {
+    Sha256::digest(value).into()
+}
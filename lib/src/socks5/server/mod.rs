@@ -1,29 +1,54 @@
 #![allow(async_fn_in_trait)]
 
+use std::{
+    net::{
+        IpAddr,
+        SocketAddr,
+    },
+    task::{
+        Context,
+        Poll,
+    },
+};
+
 use futures::{
     AsyncRead,
     AsyncWrite,
+    AsyncWriteExt as _,
+    io::Cursor,
 };
 
+use self::auth::{
+    Authenticators,
+    Identity,
+    read_username_password,
+    send_username_password_status,
+};
 use crate::{
     codec::{
         Decoder,
         Encoder,
     },
-    socks5::proto::{
-        AuthenticationMethod,
-        CommandType,
-        ConversionError,
-        Reply,
-        messages::{
-            ClientGreeting,
-            Request,
-            Response,
-            ServerChoice,
+    socks5::{
+        gssapi::MechanismError,
+        proto::{
+            Address,
+            AuthenticationMethod,
+            CommandType,
+            ConversionError,
+            Reply,
+            messages::{
+                ClientGreeting,
+                Request,
+                Response,
+                ServerChoice,
+            },
         },
     },
 };
 
+pub mod auth;
+pub mod credentials;
 #[cfg(feature = "tokio")]
 pub mod tokio;
 
@@ -43,7 +68,7 @@ pub enum ServerError {
     AuthenticationFailed,
 
     /// The client requested a command that is not supported by the server
-    /// (e.g., BIND).
+    /// (e.g., a command from an extension the server doesn't implement).
     CommandNotSupported(CommandType),
 
     /// The server failed to fulfill the client's request after authentication.
@@ -72,17 +97,97 @@ impl From<ConversionError> for ServerError {
     }
 }
 
+impl From<MechanismError> for ServerError {
+    fn from(_: MechanismError) -> Self {
+        ServerError::AuthenticationFailed
+    }
+}
+
+/// An I/O stream which can be boxed into a [BoxedStream].
+pub trait Transport: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Transport for T {}
+
+/// A type-erased I/O stream.
+///
+/// Servers using it let [Authenticator](auth::Authenticator)s replace the
+/// stream, e.g. to encapsulate the rest of the session after the
+/// sub-negotiation.
+pub struct BoxedStream(Box<dyn Transport>);
+
+impl BoxedStream {
+    pub fn new<T: Transport + 'static>(stream: T) -> Self {
+        Self(Box::new(stream))
+    }
+
+    /// Replaces the stream with the one built on top of it.
+    pub fn replace_with<T: Transport + 'static, F: FnOnce(BoxedStream) -> T>(&mut self, wrap: F) {
+        let inner = self.take();
+        *self = Self::new(wrap(inner));
+    }
+
+    /// Takes the stream out, leaving an empty one in its place.
+    ///
+    /// It's meant for wrappers which are built asynchronously, e.g. after a
+    /// TLS handshake; the result is to be put back.
+    pub fn take(&mut self) -> BoxedStream {
+        std::mem::replace(self, Self::new(Cursor::new(Vec::new())))
+    }
+}
+
+impl AsyncRead for BoxedStream {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        std::pin::Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for BoxedStream {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        std::pin::Pin::new(&mut self.get_mut().0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.get_mut().0).poll_flush(cx)
+    }
+
+    fn poll_close(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.get_mut().0).poll_close(cx)
+    }
+}
+
 /// A generic trait for a SOCKS server.
 pub trait Server<S: AsyncRead + AsyncWrite + Unpin, T = ()>: Sized {
     /// Returns a mutable reference to the underlying I/O stream.
     fn stream(&mut self) -> &mut S;
 
+    /// Returns a mutable reference to what is known about the connection.
+    fn context(&mut self) -> &mut ConnectionContext;
+
     /// Processes a single client connection through its entire lifecycle:
     /// handshake, authentication, and request handling.
+    ///
+    /// The chosen method and the client's identity are recorded in the
+    /// [Server::context] along the way.
     #[inline]
     async fn serve_client(mut self) -> Result<T, ServerError> {
         let auth_method = self.perform_handshake().await?;
-        self.authenticate(auth_method).await?;
+        self.context().method = Some(auth_method);
+        let identity = self.authenticate(auth_method).await?;
+        self.context().identity = identity;
         self.handle_request().await
     }
 
@@ -95,39 +200,27 @@ pub trait Server<S: AsyncRead + AsyncWrite + Unpin, T = ()>: Sized {
     ///
     /// In the default implementation, only
     /// [AuthenticationMethod::NO_AUTHENTICATION] is supported. This method
-    /// should be overridden to support other authentication methods.
+    /// should be overridden to support other authentication methods, e.g. by
+    /// calling [negotiate_method_impl] with another [SelectionPolicy].
     async fn perform_handshake(&mut self) -> Result<AuthenticationMethod, ServerError> {
-        let mut stream = self.stream();
-
-        let greeting = ClientGreeting::read_from(&mut stream).await?;
-        let choice = if greeting
-            .authentication_methods
-            .contains(&AuthenticationMethod::NO_AUTHENTICATION)
-        {
-            AuthenticationMethod::NO_AUTHENTICATION
-        } else {
-            return Err(ServerError::NoAcceptableAuthMethods);
-        };
-
-        let response = ServerChoice {
-            chosen_authentication_method: choice,
-        };
-        response.write_to(&mut stream).await?;
-
-        Ok(choice)
+        negotiate_method_impl(self, &SelectionPolicy::default()).await
     }
 
     /// Performs the authentication process based on the method chosen during
-    /// the handshake.
+    /// the handshake, and returns the [Identity] of the client.
     ///
     /// ### Note
     ///
     /// In the default implementation, only
     /// [AuthenticationMethod::NO_AUTHENTICATION] is handled successfully.
     /// This method should be overridden to implement other authentication
-    /// procedures, such as [AuthenticationMethod::USERNAME_PASSWORD].
-    async fn authenticate(&mut self, method: AuthenticationMethod) -> Result<(), ServerError> {
-        default_authenticate_impl(method).await
+    /// procedures, e.g. by calling [authenticate_with_impl] with a registry of
+    /// [Authenticator](auth::Authenticator)s.
+    async fn authenticate(
+        &mut self,
+        method: AuthenticationMethod,
+    ) -> Result<Identity, ServerError> {
+        default_authenticate_impl(method)
     }
 
     /// Handles the client's request after successful authentication.
@@ -137,7 +230,9 @@ pub trait Server<S: AsyncRead + AsyncWrite + Unpin, T = ()>: Sized {
     ///
     /// ### Note
     ///
-    /// In the default implementation, only [CommandType::CONNECT] is supported.
+    /// In the default implementation, [CommandType::CONNECT],
+    /// [CommandType::BIND], [CommandType::UDP_ASSOCIATE] and the Tor
+    /// [CommandType::RESOLVE] and [CommandType::RESOLVE_PTR] are dispatched.
     async fn handle_request(self) -> Result<T, ServerError> {
         default_handle_request_impl(self).await
     }
@@ -148,35 +243,538 @@ pub trait Server<S: AsyncRead + AsyncWrite + Unpin, T = ()>: Sized {
     /// specified in the request and then relaying data between the client
     /// and the target.
     async fn handle_connect(self, request: Request) -> Result<T, ServerError>;
+
+    /// Handles the [CommandType::BIND] request from the client.
+    ///
+    /// This method is responsible for opening a listening socket, reporting
+    /// its address to the client, accepting a single inbound connection and
+    /// reporting the peer's address in a second reply before relaying data.
+    ///
+    /// ### Note
+    ///
+    /// The default implementation rejects the request with
+    /// [Reply::COMMAND_NOT_SUPPORTED].
+    async fn handle_bind(mut self, request: Request) -> Result<T, ServerError> {
+        reject_command_impl(self.stream(), request.command).await
+    }
+
+    /// Handles the [CommandType::UDP_ASSOCIATE] request from the client.
+    ///
+    /// This method is responsible for opening a UDP relay, reporting its
+    /// address to the client and relaying datagrams for as long as the
+    /// controlling connection stays open.
+    ///
+    /// ### Note
+    ///
+    /// The default implementation rejects the request with
+    /// [Reply::COMMAND_NOT_SUPPORTED].
+    async fn handle_udp_associate(mut self, request: Request) -> Result<T, ServerError> {
+        reject_command_impl(self.stream(), request.command).await
+    }
+
+    /// Handles the Tor [CommandType::RESOLVE] and [CommandType::RESOLVE_PTR]
+    /// requests from the client.
+    ///
+    /// Implementations are expected to answer with [answer_resolve_impl],
+    /// which uses [Server::resolve] to get the result.
+    ///
+    /// ### Note
+    ///
+    /// The default implementation rejects the request with
+    /// [Reply::COMMAND_NOT_SUPPORTED].
+    async fn handle_resolve(mut self, request: Request) -> Result<T, ServerError> {
+        reject_command_impl(self.stream(), request.command).await
+    }
+
+    /// Resolves the address of a [CommandType::RESOLVE] or
+    /// [CommandType::RESOLVE_PTR] request.
+    ///
+    /// A domain name is expected to be resolved to an IP address for the
+    /// former, and an IP address to a domain name for the latter.
+    ///
+    /// # Errors
+    ///
+    /// Returns the [Reply] to send to the client if the address can't be
+    /// resolved. The default implementation always returns
+    /// [Reply::COMMAND_NOT_SUPPORTED].
+    async fn resolve(&mut self, request: &Request) -> Result<Address, Reply> {
+        let _ = request;
+        Err(Reply::COMMAND_NOT_SUPPORTED)
+    }
+}
+
+/// What is known about a client connection.
+///
+/// It's filled in by [Server::serve_client] as the connection goes through
+/// its lifecycle, so that request handlers can make per-client decisions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionContext {
+    /// The address of the client, if the stream has one.
+    pub peer_addr:        Option<SocketAddr>,
+    /// The address the client connected to, if the stream has one.
+    pub local_addr:       Option<SocketAddr>,
+    /// The method chosen during the handshake.
+    pub method:           Option<AuthenticationMethod>,
+    /// Who the client is, [Identity::Anonymous] until authenticated.
+    pub identity:         Identity,
+    /// The parameters sent with the
+    /// [AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK] method, if any.
+    #[cfg(feature = "json")]
+    pub parameters:       Option<serde_json::Map<String, serde_json::Value>>,
+    /// The credentials of the process on the other end of a Unix domain
+    /// socket, if known.
+    pub peer_credentials: Option<PeerCredentials>,
+}
+
+impl ConnectionContext {
+    pub const fn new(peer_addr: Option<SocketAddr>, local_addr: Option<SocketAddr>) -> Self {
+        Self {
+            peer_addr,
+            local_addr,
+            method: None,
+            identity: Identity::Anonymous,
+            #[cfg(feature = "json")]
+            parameters: None,
+            peer_credentials: None,
+        }
+    }
+}
+
+impl Default for ConnectionContext {
+    fn default() -> Self {
+        Self::new(None, None)
+    }
+}
+
+/// The credentials of a local process, as reported by the system for its end
+/// of a Unix domain socket, e.g. with `SO_PEERCRED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PeerCredentials {
+    /// The effective user ID.
+    pub user_id:    u32,
+    /// The effective group ID.
+    pub group_id:   u32,
+    /// The process ID, if the system reports it.
+    pub process_id: Option<i32>,
+}
+
+/// A range of IP addresses, e.g. `10.0.0.0/8` or `fd00::/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNetwork {
+    addr:   IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Creates a network from its address and prefix length, or returns
+    /// `None` if the prefix is longer than the address.
+    pub const fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let bits = match addr {
+            | IpAddr::V4(_) => 32,
+            | IpAddr::V6(_) => 128,
+        };
+        if prefix > bits {
+            return None;
+        }
+        Some(Self {
+            addr,
+            prefix,
+        })
+    }
+
+    /// Parses the CIDR notation, e.g. `192.168.0.0/16`, or returns `None` if
+    /// it's malformed. An address without a prefix length is a network of a
+    /// single host.
+    pub fn parse(network: &str) -> Option<Self> {
+        match network.split_once('/') {
+            | Some((addr, prefix)) => Self::new(addr.parse().ok()?, prefix.parse().ok()?),
+            | None => Some(Self::host(network.parse().ok()?)),
+        }
+    }
+
+    /// Creates a network of a single host.
+    pub const fn host(addr: IpAddr) -> Self {
+        let prefix = match addr {
+            | IpAddr::V4(_) => 32,
+            | IpAddr::V6(_) => 128,
+        };
+        Self {
+            addr,
+            prefix,
+        }
+    }
+
+    /// Returns whether the address belongs to the network.
+    ///
+    /// IPv4-mapped IPv6 addresses, e.g. `::ffff:10.0.0.1`, are treated as
+    /// IPv4 ones.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let prefix = u32::from(self.prefix);
+        match (self.addr, addr.to_canonical()) {
+            | (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shr(prefix).map_or(u32::MAX, |host| !host);
+                (u32::from(network) ^ u32::from(addr)) & mask == 0
+            },
+            | (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shr(prefix)
+                    .map_or(u128::MAX, |host| !host);
+                (u128::from(network) ^ u128::from(addr)) & mask == 0
+            },
+            | _ => false,
+        }
+    }
+}
+
+/// Decides which authentication method the server picks during the
+/// handshake.
+///
+/// The server's preference list is walked in order and the first method the
+/// client offered is picked. In the "require authentication" mode,
+/// [AuthenticationMethod::NO_AUTHENTICATION] is never picked, even if it is
+/// listed.
+///
+/// Clients from trusted networks are the exception: for them,
+/// [AuthenticationMethod::NO_AUTHENTICATION] is preferred over any other
+/// method, while everyone else is required to authenticate, see
+/// [SelectionPolicy::for_peer].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectionPolicy {
+    preference:             Box<[AuthenticationMethod]>,
+    require_authentication: bool,
+    trusted:                Box<[IpNetwork]>,
+}
+
+impl SelectionPolicy {
+    /// Creates a policy with the given methods, in the order of preference.
+    pub fn new<M: Into<Box<[AuthenticationMethod]>>>(preference: M) -> Self {
+        Self {
+            preference:             preference.into(),
+            require_authentication: false,
+            trusted:                Box::default(),
+        }
+    }
+
+    /// Adds a network whose clients are accepted without authentication.
+    pub fn with_trusted_network(mut self, network: IpNetwork) -> Self {
+        if !self.trusted.contains(&network) {
+            let mut trusted = self.trusted.into_vec();
+            trusted.push(network);
+            self.trusted = trusted.into_boxed_slice();
+        }
+        self
+    }
+
+    /// Returns the networks whose clients are accepted without
+    /// authentication.
+    pub fn trusted_networks(&self) -> &[IpNetwork] {
+        &self.trusted
+    }
+
+    /// Returns whether the address belongs to a trusted network.
+    pub fn is_trusted(&self, addr: IpAddr) -> bool {
+        self.trusted.iter().any(|network| network.contains(addr))
+    }
+
+    /// Returns the policy to apply to a client with the given address.
+    ///
+    /// If there are trusted networks, clients from them are offered
+    /// [AuthenticationMethod::NO_AUTHENTICATION] first, and the other ones
+    /// are required to authenticate. Otherwise, or for clients without an IP
+    /// address, e.g. over a Unix domain socket, the policy is unchanged.
+    pub fn for_peer(&self, peer_addr: Option<SocketAddr>) -> Self {
+        let Some(peer_addr) = peer_addr else {
+            return self.clone();
+        };
+        if self.trusted.is_empty() {
+            return self.clone();
+        }
+
+        if self.is_trusted(peer_addr.ip()) {
+            let policy = self.preference.iter().fold(
+                Self::new([AuthenticationMethod::NO_AUTHENTICATION]),
+                |policy, method| policy.with_method(*method),
+            );
+            Self {
+                trusted: self.trusted.clone(),
+                ..policy
+            }
+        } else {
+            Self {
+                require_authentication: true,
+                ..self.clone()
+            }
+        }
+    }
+
+    /// Sets whether [AuthenticationMethod::NO_AUTHENTICATION] is forbidden.
+    pub const fn require_authentication(mut self, required: bool) -> Self {
+        self.require_authentication = required;
+        self
+    }
+
+    /// Returns the methods in the order of preference.
+    pub fn preference(&self) -> &[AuthenticationMethod] {
+        &self.preference
+    }
+
+    /// Returns whether [AuthenticationMethod::NO_AUTHENTICATION] is forbidden.
+    pub const fn is_authentication_required(&self) -> bool {
+        self.require_authentication
+    }
+
+    /// Appends a method to the end of the preference list, unless it's
+    /// already there.
+    pub fn with_method(mut self, method: AuthenticationMethod) -> Self {
+        if !self.preference.contains(&method) {
+            let mut preference = self.preference.into_vec();
+            preference.push(method);
+            self.preference = preference.into_boxed_slice();
+        }
+        self
+    }
+
+    /// Returns a copy of the policy which only keeps the given methods, e.g.
+    /// the ones that have an [Authenticator](auth::Authenticator).
+    pub fn restricted_to(&self, methods: &[AuthenticationMethod]) -> Self {
+        Self {
+            preference:             self
+                .preference
+                .iter()
+                .filter(|method| methods.contains(method))
+                .copied()
+                .collect(),
+            require_authentication: self.require_authentication,
+            trusted:                self.trusted.clone(),
+        }
+    }
+
+    /// Picks a method among the ones offered by the client.
+    ///
+    /// Returns `None` if there's no acceptable method.
+    pub fn select(&self, offered: &[AuthenticationMethod]) -> Option<AuthenticationMethod> {
+        self.preference
+            .iter()
+            .filter(|method| **method != AuthenticationMethod::NO_ACCEPTABLE_METHODS)
+            .filter(|method| {
+                !(self.require_authentication
+                    && **method == AuthenticationMethod::NO_AUTHENTICATION)
+            })
+            .find(|method| offered.contains(method))
+            .copied()
+    }
+}
+
+impl Default for SelectionPolicy {
+    /// Only [AuthenticationMethod::NO_AUTHENTICATION] is accepted.
+    fn default() -> Self {
+        Self::new([AuthenticationMethod::NO_AUTHENTICATION])
+    }
+}
+
+/// The implementation of the method selection part of the
+/// [Server::perform_handshake].
+///
+/// Reads the client's greeting and replies with the method picked by the
+/// policy for the client's address, see [SelectionPolicy::for_peer]. If
+/// there's none, replies with [AuthenticationMethod::NO_ACCEPTABLE_METHODS]
+/// and closes the stream.
+///
+/// # Errors
+///
+/// Returns [ServerError::NoAcceptableAuthMethods] if the policy doesn't
+/// accept any of the offered methods, or another [ServerError] if the
+/// exchange fails.
+pub async fn negotiate_method_impl<E: Server<S, T>, S: AsyncRead + AsyncWrite + Unpin, T>(
+    server: &mut E,
+    policy: &SelectionPolicy,
+) -> Result<AuthenticationMethod, ServerError> {
+    let policy = policy.for_peer(server.context().peer_addr);
+    let mut stream = server.stream();
+
+    let greeting = ClientGreeting::read_from(&mut stream).await?;
+    let choice = policy.select(&greeting.authentication_methods);
+
+    let response = ServerChoice {
+        chosen_authentication_method: choice.unwrap_or(AuthenticationMethod::NO_ACCEPTABLE_METHODS),
+    };
+    response.write_to(&mut stream).await?;
+
+    match choice {
+        | Some(method) => Ok(method),
+        | None => {
+            stream.close().await?;
+            Err(ServerError::NoAcceptableAuthMethods)
+        },
+    }
 }
 
 /// The default implementation for the [Server::authenticate] method.
 /// Only allows the [AuthenticationMethod::NO_AUTHENTICATION] method.
+///
+/// # Errors
+///
+/// Returns [ServerError::NoAcceptableAuthMethods] for any other method.
 #[inline]
-pub async fn default_authenticate_impl(method: AuthenticationMethod) -> Result<(), ServerError> {
+pub const fn default_authenticate_impl(
+    method: AuthenticationMethod,
+) -> Result<Identity, ServerError> {
     match method {
-        | AuthenticationMethod::NO_AUTHENTICATION => Ok(()),
+        | AuthenticationMethod::NO_AUTHENTICATION => Ok(Identity::Anonymous),
         | _ => Err(ServerError::NoAcceptableAuthMethods),
     }
 }
 
+/// An implementation of the [Server::authenticate] method which runs the
+/// [Authenticator](auth::Authenticator) registered for the chosen method with
+/// the server's [ConnectionContext].
+///
+/// Without an authenticator, [AuthenticationMethod::NO_AUTHENTICATION] falls
+/// back to [default_authenticate_impl], which accepts the client as
+/// [Identity::Anonymous]: that's how clients from trusted networks get in.
+/// Servers calling this function must thus only pick that method, during the
+/// handshake, for the clients they mean to let in without authentication,
+/// e.g. with a [SelectionPolicy] requiring authentication.
+///
+/// # Errors
+///
+/// Returns [ServerError::NoAcceptableAuthMethods] if no authenticator is
+/// registered for the method, or the error of the authenticator.
+pub async fn authenticate_with_impl<
+    E: Server<S, T>,
+    S: AsyncRead + AsyncWrite + Unpin + Send,
+    T,
+>(
+    server: &mut E,
+    authenticators: &Authenticators<S>,
+    method: AuthenticationMethod,
+) -> Result<Identity, ServerError> {
+    let Some(authenticator) = authenticators.get(method) else {
+        return default_authenticate_impl(method);
+    };
+
+    let mut context = server.context().clone();
+    let identity = authenticator
+        .authenticate(server.stream(), &mut context)
+        .await;
+    *server.context() = context;
+    identity
+}
+
+/// The implementation of the [AuthenticationMethod::USERNAME_PASSWORD]
+/// sub-negotiation.
+///
+/// Reads the client's credentials, checks them with `verify` and replies with
+/// the sub-negotiation status. On failure, the stream is closed.
+///
+/// On success, returns the [Identity::User] with the client's username.
+///
+/// # Errors
+///
+/// Returns [ServerError::AuthenticationFailed] if `verify` rejects the
+/// credentials, or another [ServerError] if the exchange fails.
+pub async fn username_password_auth_impl<
+    E: Server<S, T>,
+    S: AsyncRead + AsyncWrite + Unpin,
+    T,
+    F: FnOnce(&[u8], &[u8]) -> bool,
+>(
+    server: &mut E,
+    verify: F,
+) -> Result<Identity, ServerError> {
+    let stream = server.stream();
+
+    let auth_request = read_username_password(stream).await?;
+    let accepted = verify(&auth_request.username, &auth_request.password);
+    send_username_password_status(stream, accepted).await?;
+
+    Ok(Identity::User(auth_request.username))
+}
+
 /// The default implementation for the [Server::handle_request] method.
 ///
-/// Reads the request and dispatches it. Only supports the
-/// [CommandType::CONNECT] command.
+/// Reads the request and dispatches it with [dispatch_request_impl].
 #[inline]
 pub async fn default_handle_request_impl<E: Server<S, T>, S: AsyncRead + AsyncWrite + Unpin, T>(
     mut server: E,
 ) -> Result<T, ServerError> {
-    let mut stream = server.stream();
+    let request = Request::read_from(server.stream()).await?;
+    dispatch_request_impl(server, request).await
+}
 
-    let request = Request::read_from(&mut stream).await?;
+/// Dispatches a request to the handler of its command.
+///
+/// Only supports the
+/// [CommandType::CONNECT], [CommandType::BIND], [CommandType::UDP_ASSOCIATE],
+/// [CommandType::RESOLVE] and [CommandType::RESOLVE_PTR] commands.
+///
+/// # Errors
+///
+/// Returns a [ServerError] if the command is unknown or the handler fails.
+#[inline]
+pub async fn dispatch_request_impl<E: Server<S, T>, S: AsyncRead + AsyncWrite + Unpin, T>(
+    mut server: E,
+    request: Request,
+) -> Result<T, ServerError> {
     match request.command {
         | CommandType::CONNECT => server.handle_connect(request).await,
-        | _ => {
-            let response = Response::UNSUPPORTED_COMMAND;
-            response.write_to(&mut stream).await?;
-            Err(ServerError::CommandNotSupported(request.command))
+        | CommandType::BIND => server.handle_bind(request).await,
+        | CommandType::UDP_ASSOCIATE => server.handle_udp_associate(request).await,
+        | CommandType::RESOLVE | CommandType::RESOLVE_PTR => server.handle_resolve(request).await,
+        | _ => reject_command_impl(server.stream(), request.command).await,
+    }
+}
+
+/// Answers a [CommandType::RESOLVE] or [CommandType::RESOLVE_PTR] request
+/// with the result of [Server::resolve].
+///
+/// # Errors
+///
+/// Returns [ServerError::RequestFailed] if the address can't be resolved, or
+/// an I/O error if the reply can't be written.
+pub async fn answer_resolve_impl<E: Server<S, T>, S: AsyncRead + AsyncWrite + Unpin, T>(
+    server: &mut E,
+    request: Request,
+) -> Result<(), ServerError> {
+    let response = match server.resolve(&request).await {
+        | Ok(address) => {
+            Response {
+                reply: Reply::SUCCESS,
+                address,
+                port: 0,
+            }
+        },
+        | Err(reply) => {
+            Response {
+                reply,
+                address: Address::Ipv4(std::net::Ipv4Addr::UNSPECIFIED),
+                port: 0,
+            }
         },
+    };
+    response.write_to(server.stream()).await?;
+
+    if response.reply.is_success() {
+        Ok(())
+    } else {
+        Err(ServerError::RequestFailed(response.reply))
     }
 }
+
+/// Replies with [Response::UNSUPPORTED_COMMAND] and fails with
+/// [ServerError::CommandNotSupported].
+///
+/// # Errors
+///
+/// Always returns an error: either the I/O error from writing the reply or
+/// [ServerError::CommandNotSupported].
+#[inline]
+pub async fn reject_command_impl<S: AsyncWrite + Unpin, T>(
+    stream: &mut S,
+    command: CommandType,
+) -> Result<T, ServerError> {
+    let response = Response::UNSUPPORTED_COMMAND;
+    response.write_to(stream).await?;
+    Err(ServerError::CommandNotSupported(command))
+}
@@ -1,40 +1,91 @@
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(feature = "tls")]
+use std::time::Duration;
 use std::{
-    io::Result as IoResult,
+    io::{
+        Error as IoError,
+        ErrorKind,
+        Result as IoResult,
+    },
+    net::{
+        IpAddr,
+        SocketAddr,
+    },
     sync::Arc,
+    time::Instant,
 };
 
+use futures::AsyncReadExt as _;
+#[cfg(unix)]
+use tokio::net::UnixListener;
 use tokio::net::{
     TcpListener,
     TcpStream,
     ToSocketAddrs,
+    UdpSocket,
+    lookup_host,
 };
+#[cfg(feature = "tls")]
+use tokio_rustls::server::TlsStream;
 use tokio_util::compat::{
-    Compat,
+    FuturesAsyncReadCompatExt as _,
     TokioAsyncReadCompatExt,
 };
 
-#[allow(unused, reason = "docs")]
 use super::CommandType;
+#[cfg(feature = "tls")]
+use crate::socks5::tls::{
+    Acceptor,
+    ClientCertificate,
+};
 use crate::{
     codec::{
-        Decoder,
-        Encoder,
+        Decoder as _,
+        Encoder as _,
+    },
+    socks4::{
+        self,
+        server::{
+            Server as _,
+            tokio::Socks4Server,
+        },
     },
     socks5::{
         proto::{
             Address,
             AuthenticationMethod,
+            ConversionError,
             Reply,
             messages::{
                 Request,
                 Response,
-                auth::username_password,
+                UdpHeader,
+            },
+            reassembly::{
+                self,
+                Reassembler,
             },
         },
         server::{
+            BoxedStream,
+            ConnectionContext,
+            IpNetwork,
+            PeerCredentials,
+            SelectionPolicy,
             Server,
             ServerError,
-            default_authenticate_impl,
+            answer_resolve_impl,
+            auth::{
+                Authenticator,
+                Authenticators,
+                Identity,
+                UsernamePassword,
+            },
+            authenticate_with_impl,
+            dispatch_request_impl,
+            negotiate_method_impl,
+            reject_command_impl,
         },
     },
 };
@@ -42,9 +93,35 @@ use crate::{
 type CredentialsHolder = Arc<(Box<[u8]>, Box<[u8]>)>;
 
 /// A Tokio-based SOCKS5 server listener.
+///
+/// The listener can also serve SOCKS4 and SOCKS4a clients on the same port,
+/// see [Socks5Listener::with_socks4].
 pub struct Socks5Listener {
-    listener:    TcpListener,
-    credentials: CredentialsHolder,
+    listener: Listener,
+    settings: Arc<Settings>,
+    socks4:   bool,
+    #[cfg(feature = "tls")]
+    acceptor: Option<Acceptor>,
+    #[cfg(feature = "tls")]
+    timeout:  Duration,
+    #[cfg(feature = "tls")]
+    mapper:   Option<Arc<CertificateMapper>>,
+}
+
+/// Maps a client certificate to an identity, see
+/// [Socks5Listener::with_client_certificates].
+#[cfg(feature = "tls")]
+type CertificateMapper = dyn Fn(&ClientCertificate) -> Option<Identity> + Send + Sync;
+
+/// Tells whether a request may be served, see
+/// [Socks5Listener::with_request_filter].
+type RequestFilter = dyn Fn(&ConnectionContext, &Request) -> bool + Send + Sync;
+
+/// The socket a [Socks5Listener] accepts connections on.
+enum Listener {
+    TcpSocket(TcpListener),
+    #[cfg(unix)]
+    UnixSocket(UnixListener),
 }
 
 impl Socks5Listener {
@@ -54,21 +131,302 @@ impl Socks5Listener {
     /// address.
     pub async fn bind<A: ToSocketAddrs>(addr: A, credentials: CredentialsHolder) -> IoResult<Self> {
         let listener = TcpListener::bind(addr).await?;
+        Ok(Self::with_listener(
+            Listener::TcpSocket(listener),
+            Settings::new(credentials),
+        ))
+    }
+
+    /// Binds without registering [UsernamePassword], so that every client is
+    /// refused until an authenticator is registered with
+    /// [Socks5Listener::with_authenticator].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [std::io::Result] error if the listener fails to bind to the
+    /// address.
+    pub async fn bind_without_credentials<A: ToSocketAddrs>(addr: A) -> IoResult<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self::with_listener(
+            Listener::TcpSocket(listener),
+            Settings::without_credentials(),
+        ))
+    }
+
+    /// Binds to a Unix domain socket, e.g. for local sidecar deployments.
+    ///
+    /// The credentials of the connecting processes are recorded in
+    /// [ConnectionContext::peer_credentials], so that they can be authorized
+    /// with [LocalPeer](super::auth::LocalPeer) instead of passwords; set a
+    /// selection policy with [AuthenticationMethod::NO_AUTHENTICATION] alone
+    /// to not accept passwords at all. Connections have no IP address, so
+    /// [CommandType::BIND] and [CommandType::UDP_ASSOCIATE] are rejected,
+    /// and neither SOCKS4 nor TLS is served.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [std::io::Result] error if the listener fails to bind to the
+    /// path, e.g. if the file already exists.
+    #[cfg(unix)]
+    pub fn bind_unix<P: AsRef<Path>>(path: P, credentials: CredentialsHolder) -> IoResult<Self> {
+        let listener = UnixListener::bind(path)?;
+        Ok(Self::with_listener(
+            Listener::UnixSocket(listener),
+            Settings::new(credentials),
+        ))
+    }
 
-        Ok(Self {
+    /// Binds to a Unix domain socket without registering [UsernamePassword],
+    /// see [Socks5Listener::bind_unix] and
+    /// [Socks5Listener::bind_without_credentials].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [std::io::Result] error if the listener fails to bind to the
+    /// path, e.g. if the file already exists.
+    #[cfg(unix)]
+    pub fn bind_unix_without_credentials<P: AsRef<Path>>(path: P) -> IoResult<Self> {
+        let listener = UnixListener::bind(path)?;
+        Ok(Self::with_listener(
+            Listener::UnixSocket(listener),
+            Settings::without_credentials(),
+        ))
+    }
+
+    fn with_listener(listener: Listener, settings: Settings) -> Self {
+        Self {
             listener,
-            credentials,
-        })
+            settings: Arc::new(settings),
+            socks4: false,
+            #[cfg(feature = "tls")]
+            acceptor: None,
+            #[cfg(feature = "tls")]
+            timeout: Duration::from_secs(10),
+            #[cfg(feature = "tls")]
+            mapper: None,
+        }
+    }
+
+    /// Wraps every connection in TLS, so that the whole session, credentials
+    /// included, is encrypted.
+    ///
+    /// SOCKS4 clients are not served over TLS: [Socks5Listener::with_socks4]
+    /// has no effect then, and a warning is logged when the listener starts.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, acceptor: Acceptor) -> Self {
+        self.acceptor = Some(acceptor);
+        self
+    }
+
+    /// Sets how long clients have to complete the TLS handshake before being
+    /// disconnected.
+    ///
+    /// By default, they have 10 seconds.
+    #[cfg(feature = "tls")]
+    pub const fn with_tls_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how client certificates map to identities.
+    ///
+    /// If the TLS acceptor verifies client certificates, e.g. one built with
+    /// [acceptor_with_client_auth](crate::socks5::tls::acceptor_with_client_auth),
+    /// a client with a certificate is authenticated before the handshake:
+    /// [AuthenticationMethod::NO_AUTHENTICATION] is then accepted even if the
+    /// selection policy requires authentication. Clients whose certificate
+    /// maps to `None` are disconnected.
+    ///
+    /// By default, certificates map to an [Identity::Certificate] named after
+    /// [ClientCertificate::name].
+    #[cfg(feature = "tls")]
+    pub fn with_client_certificates<F>(mut self, mapper: F) -> Self
+    where
+        F: Fn(&ClientCertificate) -> Option<Identity> + Send + Sync + 'static,
+    {
+        self.mapper = Some(Arc::new(mapper));
+        self
+    }
+
+    /// Enables serving SOCKS4 and SOCKS4a clients alongside with SOCKS5 ones.
+    ///
+    /// The protocol version is detected from the first byte of each
+    /// connection, and SOCKS4 connections are handed to a [Socks4Server].
+    ///
+    /// ### Note
+    ///
+    /// SOCKS4 has no authentication, so its clients are only served if the
+    /// selection policy would let them in with
+    /// [AuthenticationMethod::NO_AUTHENTICATION], e.g. from a trusted network,
+    /// and the authenticator registered for it, if any, accepts them. The
+    /// other ones have their request rejected.
+    pub const fn with_socks4(mut self, enabled: bool) -> Self {
+        self.socks4 = enabled;
+        self
+    }
+
+    /// Sets the limits for reassembling fragmented UDP datagrams.
+    pub fn with_reassembly(mut self, config: reassembly::Config) -> Self {
+        Arc::make_mut(&mut self.settings).reassembly = config;
+        self
+    }
+
+    /// Sets the policy for picking the authentication method.
+    ///
+    /// By default, only [AuthenticationMethod::USERNAME_PASSWORD] is accepted.
+    /// Methods without a registered [Authenticator] are never picked.
+    pub fn with_selection_policy(mut self, policy: SelectionPolicy) -> Self {
+        Arc::make_mut(&mut self.settings).policy = policy;
+        self
+    }
+
+    /// Accepts the clients from the network without authentication, while
+    /// the other ones still have to authenticate, see
+    /// [SelectionPolicy::for_peer].
+    pub fn with_trusted_network(mut self, network: IpNetwork) -> Self {
+        let settings = Arc::make_mut(&mut self.settings);
+        settings.policy = settings.policy.clone().with_trusted_network(network);
+        self
+    }
+
+    /// Registers an authenticator and appends its method to the selection
+    /// policy.
+    ///
+    /// Registering an authenticator for
+    /// [AuthenticationMethod::NO_AUTHENTICATION] lifts the requirement to
+    /// authenticate. By default, only [UsernamePassword] with the listener's
+    /// credentials is registered.
+    pub fn with_authenticator<A: Authenticator<BoxedStream> + 'static>(
+        mut self,
+        authenticator: A,
+    ) -> Self {
+        Arc::make_mut(&mut self.settings).register(authenticator);
+        self
+    }
+
+    /// Sets which requests are served, given what is known about the
+    /// connection, e.g. the identity of the client and the method it
+    /// authenticated with. The other ones are rejected with
+    /// [Reply::CONNECTION_NOT_ALLOWED_BY_RULESET].
+    ///
+    /// SOCKS4 requests are filtered as the SOCKS5 requests with the same
+    /// command and target, and rejected with the protocol's only failure
+    /// reply.
+    ///
+    /// By default, every request is served.
+    pub fn with_request_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ConnectionContext, &Request) -> bool + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.settings).filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Returns the local address that this listener is bound to.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [std::io::Result] error if the address can't be retrieved,
+    /// e.g. if the listener is bound to a Unix domain socket.
+    pub fn local_addr(&self) -> IoResult<SocketAddr> {
+        match &self.listener {
+            | Listener::TcpSocket(listener) => listener.local_addr(),
+            #[cfg(unix)]
+            | Listener::UnixSocket(_) => Err(IoError::from(ErrorKind::Unsupported)),
+        }
     }
 
     /// Starts the main server loop, accepting and handling connections
     /// indefinitely.
     pub async fn run(&self) -> IoResult<()> {
+        let listener = match &self.listener {
+            | Listener::TcpSocket(listener) => listener,
+            #[cfg(unix)]
+            | Listener::UnixSocket(listener) => return self.run_unix(listener).await,
+        };
+
+        #[cfg(feature = "tls")]
+        if self.socks4 && self.acceptor.is_some() {
+            log::warn!("SOCKS4 clients are not served over TLS, only SOCKS5 ones are");
+        }
+
+        loop {
+            let (stream, peer_addr) = listener.accept().await?;
+            let settings = Arc::clone(&self.settings);
+            let socks4 = self.socks4;
+            #[cfg(feature = "tls")]
+            let acceptor = self.acceptor.clone();
+            #[cfg(feature = "tls")]
+            let timeout = self.timeout;
+            #[cfg(feature = "tls")]
+            let mapper = self.mapper.clone();
+            tokio::spawn(async move {
+                let mut context = ConnectionContext::new(Some(peer_addr), stream.local_addr().ok());
+
+                #[cfg(feature = "tls")]
+                if let Some(acceptor) = acceptor {
+                    let Ok(Ok(stream)) =
+                        tokio::time::timeout(timeout, acceptor.accept(stream)).await
+                    else {
+                        return;
+                    };
+                    let Some(identity) = certificate_identity(&stream, mapper.as_deref()) else {
+                        return;
+                    };
+                    context.identity = identity;
+                    let stream = BoxedStream::new(stream.compat());
+                    let _ = Socks5Server::with_settings(stream, context, settings)
+                        .serve_client()
+                        .await;
+                    return;
+                }
+
+                let mut version = [0; 1];
+                if socks4
+                    && matches!(stream.peek(&mut version).await, Ok(1))
+                    && version == [socks4::proto::VERSION]
+                {
+                    if settings.admits_socks4(&mut context).await {
+                        let _ = serve_socks4(stream, &context, &settings).await;
+                    } else {
+                        let _ = socks4::server::reject_request_impl::<_, ()>(&mut stream.compat())
+                            .await;
+                    }
+                    return;
+                }
+
+                let stream = BoxedStream::new(stream.compat());
+                let _ = Socks5Server::with_settings(stream, context, settings)
+                    .serve_client()
+                    .await;
+            });
+        }
+    }
+
+    /// Accepts and handles connections on a Unix domain socket indefinitely.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [std::io::Result] error if a connection can't be accepted.
+    #[cfg(unix)]
+    async fn run_unix(&self, listener: &UnixListener) -> IoResult<()> {
         loop {
-            let (stream, _peer_addr) = self.listener.accept().await?;
-            let creds = self.credentials.clone();
+            let (stream, _) = listener.accept().await?;
+            let settings = Arc::clone(&self.settings);
             tokio::spawn(async move {
-                let _ = Socks5Server::new(stream, creds).serve_client().await;
+                let mut context = ConnectionContext::new(None, None);
+                context.peer_credentials = stream.peer_cred().ok().map(|credentials| {
+                    PeerCredentials {
+                        user_id:    credentials.uid(),
+                        group_id:   credentials.gid(),
+                        process_id: credentials.pid(),
+                    }
+                });
+
+                let stream = BoxedStream::new(stream.compat());
+                let _ = Socks5Server::with_settings(stream, context, settings)
+                    .serve_client()
+                    .await;
             });
         }
     }
@@ -77,28 +435,190 @@ impl Socks5Listener {
 /// A Tokio-based SOCKS server for handling a single client connection.
 ///
 /// The [Server] implementation for this struct provides these capabilities:
-/// * [AuthenticationMethod::USERNAME_PASSWORD] authentication;
-/// * [CommandType::CONNECT] command support.
+/// * [AuthenticationMethod::USERNAME_PASSWORD] authentication and custom
+///   [Authenticator]s;
+/// * [CommandType::CONNECT] command support;
+/// * [CommandType::BIND] command support;
+/// * [CommandType::UDP_ASSOCIATE] command support;
+/// * Tor [CommandType::RESOLVE] command support.
 pub struct Socks5Server {
-    stream:      Compat<TcpStream>,
-    credentials: CredentialsHolder,
+    stream:   BoxedStream,
+    context:  ConnectionContext,
+    settings: Arc<Settings>,
 }
 
 impl Socks5Server {
     pub fn new(stream: TcpStream, credentials: CredentialsHolder) -> Self {
+        Self::with_tcp_stream(stream, Settings::new(credentials))
+    }
+
+    /// Creates a server without registering [UsernamePassword], so that the
+    /// client is refused unless an authenticator is registered with
+    /// [Socks5Server::with_authenticator].
+    pub fn without_credentials(stream: TcpStream) -> Self {
+        Self::with_tcp_stream(stream, Settings::without_credentials())
+    }
+
+    fn with_tcp_stream(stream: TcpStream, settings: Settings) -> Self {
+        let context = ConnectionContext::new(stream.peer_addr().ok(), stream.local_addr().ok());
+        let stream = BoxedStream::new(stream.compat());
+        Self::with_settings(stream, context, Arc::new(settings))
+    }
+
+    const fn with_settings(
+        stream: BoxedStream,
+        context: ConnectionContext,
+        settings: Arc<Settings>,
+    ) -> Self {
         Self {
-            stream: stream.compat(),
-            credentials,
+            stream,
+            context,
+            settings,
+        }
+    }
+
+    /// Sets the limits for reassembling fragmented UDP datagrams.
+    pub fn with_reassembly(mut self, config: reassembly::Config) -> Self {
+        Arc::make_mut(&mut self.settings).reassembly = config;
+        self
+    }
+
+    /// Sets the policy for picking the authentication method.
+    ///
+    /// By default, only [AuthenticationMethod::USERNAME_PASSWORD] is accepted.
+    /// Methods without a registered [Authenticator] are never picked.
+    pub fn with_selection_policy(mut self, policy: SelectionPolicy) -> Self {
+        Arc::make_mut(&mut self.settings).policy = policy;
+        self
+    }
+
+    /// Accepts the clients from the network without authentication, while
+    /// the other ones still have to authenticate, see
+    /// [SelectionPolicy::for_peer].
+    pub fn with_trusted_network(mut self, network: IpNetwork) -> Self {
+        let settings = Arc::make_mut(&mut self.settings);
+        settings.policy = settings.policy.clone().with_trusted_network(network);
+        self
+    }
+
+    /// Registers an authenticator and appends its method to the selection
+    /// policy.
+    ///
+    /// Registering an authenticator for
+    /// [AuthenticationMethod::NO_AUTHENTICATION] lifts the requirement to
+    /// authenticate. By default, only [UsernamePassword] with the server's
+    /// credentials is registered.
+    pub fn with_authenticator<A: Authenticator<BoxedStream> + 'static>(
+        mut self,
+        authenticator: A,
+    ) -> Self {
+        Arc::make_mut(&mut self.settings).register(authenticator);
+        self
+    }
+
+    /// Sets which requests are served, given what is known about the
+    /// connection, e.g. the identity of the client and the method it
+    /// authenticated with. The other ones are rejected with
+    /// [Reply::CONNECTION_NOT_ALLOWED_BY_RULESET].
+    ///
+    /// By default, every request is served.
+    pub fn with_request_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&ConnectionContext, &Request) -> bool + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.settings).filter = Some(Arc::new(filter));
+        self
+    }
+}
+
+/// Options shared by the connections of a [Socks5Listener].
+#[derive(Clone)]
+struct Settings {
+    authenticators: Authenticators<BoxedStream>,
+    reassembly:     reassembly::Config,
+    policy:         SelectionPolicy,
+    filter:         Option<Arc<RequestFilter>>,
+}
+
+impl Settings {
+    /// Only [UsernamePassword] with the given credentials is registered.
+    fn new(credentials: CredentialsHolder) -> Self {
+        let mut settings = Self::without_credentials();
+        settings.register(UsernamePassword::new(credentials));
+        settings
+    }
+
+    /// No authenticator is registered, so no method can be picked.
+    fn without_credentials() -> Self {
+        Self {
+            authenticators: Authenticators::new(),
+            reassembly:     reassembly::Config::default(),
+            policy:         SelectionPolicy::new([]).require_authentication(true),
+            filter:         None,
+        }
+    }
+
+    /// Tells whether a SOCKS4 client may be served, as the protocol can't
+    /// authenticate: only if [AuthenticationMethod::NO_AUTHENTICATION] would
+    /// be picked for it, and the authenticator registered for that method, if
+    /// any, accepts it.
+    async fn admits_socks4(&self, context: &mut ConnectionContext) -> bool {
+        let available = self.authenticators.methods();
+        let policy = self
+            .policy
+            .restricted_to(&available)
+            .for_peer(context.peer_addr);
+        if policy
+            .select(&[AuthenticationMethod::NO_AUTHENTICATION])
+            .is_none()
+        {
+            return false;
+        }
+
+        let Some(authenticator) = self
+            .authenticators
+            .get(AuthenticationMethod::NO_AUTHENTICATION)
+        else {
+            return true;
+        };
+        // The method has no sub-negotiation, so the authenticator only gets
+        // to look at the context.
+        let mut stream = BoxedStream::new(futures::io::Cursor::new(Vec::new()));
+        authenticator
+            .authenticate(&mut stream, context)
+            .await
+            .is_ok()
+    }
+
+    /// Tells whether the filter, if any, accepts the request.
+    fn accepts(&self, context: &ConnectionContext, request: &Request) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter(context, request))
+    }
+
+    fn register<A: Authenticator<BoxedStream> + 'static>(&mut self, authenticator: A) {
+        let method = authenticator.method();
+        let mut policy = self.policy.clone().with_method(method);
+        if method == AuthenticationMethod::NO_AUTHENTICATION {
+            policy = policy.require_authentication(false);
         }
+        self.policy = policy;
+        self.authenticators.register(authenticator);
     }
 }
 
-impl Server<Compat<TcpStream>> for Socks5Server {
+impl Server<BoxedStream> for Socks5Server {
     #[inline]
-    fn stream(&mut self) -> &mut Compat<TcpStream> {
+    fn stream(&mut self) -> &mut BoxedStream {
         &mut self.stream
     }
 
+    #[inline]
+    fn context(&mut self) -> &mut ConnectionContext {
+        &mut self.context
+    }
+
     /// Handles a [CommandType::CONNECT] request from a SOCKS client.
     ///
     /// # Errors
@@ -136,46 +656,432 @@ impl Server<Compat<TcpStream>> for Socks5Server {
         };
         response.write_to(self.stream()).await?;
 
-        let (mut client_reader, mut client_writer) = tokio::io::split(self.stream.into_inner());
-        let (mut target_reader, mut target_writer) = tokio::io::split(target_stream);
-        let client_to_target = tokio::io::copy(&mut client_reader, &mut target_writer);
-        let target_to_client = tokio::io::copy(&mut target_reader, &mut client_writer);
+        relay(self.stream, target_stream).await;
+
+        Ok(())
+    }
+
+    /// Handles a [CommandType::BIND] request from a SOCKS client.
+    ///
+    /// The listening socket is opened on the same local address the client
+    /// connected to. If the request carries a specified IP address, only a
+    /// peer with that address is accepted.
+    ///
+    /// The request is rejected on connections without an IP address, e.g.
+    /// over a Unix domain socket.
+    ///
+    /// # Errors
+    /// Returns a [ServerError] if the listening socket cannot be opened, the
+    /// inbound peer is not the expected one or if there are I/O errors during
+    /// communication.
+    async fn handle_bind(mut self, request: Request) -> Result<(), super::ServerError> {
+        if self.context.local_addr.is_none() {
+            return reject_command_impl(self.stream(), request.command).await;
+        }
+        let local_ip = local_addr(&self.context)?.ip();
+
+        let listener = match TcpListener::bind((local_ip, 0)).await {
+            | Ok(bound) => bound,
+            | Err(_) => {
+                Response::GENERAL_FAILURE.write_to(self.stream()).await?;
+                return Err(ServerError::RequestFailed(Reply::GENERAL_FAILURE));
+            },
+        };
+
+        let bound_addr = listener.local_addr()?;
+        let first_response = Response {
+            reply:   Reply::SUCCESS,
+            address: bound_addr.ip().into(),
+            port:    bound_addr.port(),
+        };
+        first_response.write_to(self.stream()).await?;
+
+        let (peer_stream, peer_addr) = match listener.accept().await {
+            | Ok(accepted) => accepted,
+            | Err(_) => {
+                Response::GENERAL_FAILURE.write_to(self.stream()).await?;
+                return Err(ServerError::RequestFailed(Reply::GENERAL_FAILURE));
+            },
+        };
+        drop(listener);
 
-        tokio::select! {
-            _ = client_to_target => {},
-            _ = target_to_client => {},
+        let expected_ip = match request.address {
+            | Address::Ipv4(addr) if !addr.is_unspecified() => Some(IpAddr::V4(addr)),
+            | Address::Ipv6(addr) if !addr.is_unspecified() => Some(IpAddr::V6(addr)),
+            | _ => None,
+        };
+        if expected_ip.is_some_and(|expected| expected != peer_addr.ip()) {
+            Response::NOT_ALLOWED_BY_RULESET
+                .write_to(self.stream())
+                .await?;
+            return Err(ServerError::RequestFailed(
+                Reply::CONNECTION_NOT_ALLOWED_BY_RULESET,
+            ));
         }
 
+        let second_response = Response {
+            reply:   Reply::SUCCESS,
+            address: peer_addr.ip().into(),
+            port:    peer_addr.port(),
+        };
+        second_response.write_to(self.stream()).await?;
+
+        relay(self.stream, peer_stream).await;
+
         Ok(())
     }
 
-    /// Overrides the default `authenticate` method to support
-    /// [AuthenticationMethod::USERNAME_PASSWORD] auth alongside with the
-    /// [AuthenticationMethod::NO_AUTHENTICATION].
+    /// Handles a [CommandType::UDP_ASSOCIATE] request from a SOCKS client.
+    ///
+    /// The relay socket is opened on the same local address the client
+    /// connected to. Datagrams are accepted only from the IP address of the
+    /// controlling connection (and from the port given in the request, if
+    /// specified), whatever the address in the request is; the first accepted
+    /// datagram pins the association to its source. The association is torn
+    /// down once the controlling connection is closed. Errors receiving a
+    /// datagram are logged and don't end the association.
+    ///
+    /// Fragmented datagrams are reassembled according to the server's
+    /// [reassembly::Config] before being relayed; incomplete queues are dropped
+    /// as soon as their timer expires.
+    ///
+    /// Datagrams can't be encapsulated, so the request is rejected on
+    /// [AuthenticationMethod::GSSAPI] sessions. It's also rejected on
+    /// connections without an IP address, e.g. over a Unix domain socket.
+    ///
+    /// # Errors
+    /// Returns a [ServerError] if the relay socket cannot be opened or if there
+    /// are I/O errors during communication.
+    async fn handle_udp_associate(mut self, request: Request) -> Result<(), super::ServerError> {
+        if self.context.method == Some(AuthenticationMethod::GSSAPI)
+            || self.context.local_addr.is_none()
+        {
+            return reject_command_impl(self.stream(), request.command).await;
+        }
+
+        let local_ip = local_addr(&self.context)?.ip();
+        // The address in the request can't be trusted to designate the
+        // client, only the port is taken from there.
+        let client_ip = self
+            .context
+            .peer_addr
+            .ok_or_else(|| IoError::from(ErrorKind::NotConnected))?
+            .ip();
+        let client_port = request.port;
+
+        let socket = match UdpSocket::bind((local_ip, 0)).await {
+            | Ok(bound) => bound,
+            | Err(_) => {
+                Response::GENERAL_FAILURE.write_to(self.stream()).await?;
+                return Err(ServerError::RequestFailed(Reply::GENERAL_FAILURE));
+            },
+        };
+
+        let bound_addr = socket.local_addr()?;
+        let response = Response {
+            reply:   Reply::SUCCESS,
+            address: bound_addr.ip().into(),
+            port:    bound_addr.port(),
+        };
+        response.write_to(self.stream()).await?;
+
+        let mut control_stream = self.stream;
+        let mut control_buf = [0; 1];
+        let mut datagram_buf = vec![0; usize::from(u16::MAX)];
+        let mut client_addr: Option<SocketAddr> = None;
+        let mut reassembler = Reassembler::new(self.settings.reassembly);
+
+        loop {
+            tokio::select! {
+                read = control_stream.read(&mut control_buf) => match read {
+                    | Ok(0) | Err(_) => break,
+                    | Ok(_) => continue,
+                },
+                () = expiry(reassembler.deadline()) => reassembler.expire(),
+                received = socket.recv_from(&mut datagram_buf) => {
+                    let (length, source) = match received {
+                        | Ok(received) => received,
+                        | Err(error) => {
+                            log::warn!("failed to receive a relayed datagram: {error}");
+                            continue;
+                        },
+                    };
+                    let Some(datagram) = datagram_buf.get(.. length) else {
+                        continue;
+                    };
+
+                    let is_client = match client_addr {
+                        | Some(addr) => addr == source,
+                        | None => {
+                            source.ip() == client_ip
+                                && (client_port == 0 || source.port() == client_port)
+                        },
+                    };
+
+                    if is_client {
+                        client_addr = Some(source);
+
+                        let Ok((header, payload)) = UdpHeader::parse(datagram) else {
+                            continue;
+                        };
+                        let Some(complete) = reassembler.push(&header, payload) else {
+                            continue;
+                        };
+                        let target = resolve(&complete.header.address, complete.header.port).await;
+                        if let Some(target) = target {
+                            let _ = socket.send_to(&complete.data, target).await;
+                        }
+                        continue;
+                    }
+
+                    let Some(client) = client_addr else {
+                        continue;
+                    };
+                    if let Ok(packet) = encapsulate(source, datagram) {
+                        let _ = socket.send_to(&packet, client).await;
+                    }
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles the Tor [CommandType::RESOLVE] and [CommandType::RESOLVE_PTR]
+    /// requests from a SOCKS client.
+    ///
+    /// # Errors
+    /// Returns a [ServerError] if the address can't be resolved or if there are
+    /// I/O errors during communication.
+    async fn handle_resolve(mut self, request: Request) -> Result<(), super::ServerError> {
+        answer_resolve_impl(&mut self, request).await
+    }
+
+    /// Resolves domain names with the system resolver.
+    ///
+    /// Reverse lookups are not available there, so [CommandType::RESOLVE_PTR]
+    /// is answered with [Reply::COMMAND_NOT_SUPPORTED].
+    ///
+    /// # Errors
+    /// Returns [Reply::HOST_UNREACHABLE] if the domain name can't be resolved.
+    async fn resolve(&mut self, request: &Request) -> Result<Address, Reply> {
+        if request.command != CommandType::RESOLVE {
+            return Err(Reply::COMMAND_NOT_SUPPORTED);
+        }
+
+        let address = if matches!(request.address, Address::Domain(_)) {
+            resolve(&request.address, 0)
+                .await
+                .ok_or(Reply::HOST_UNREACHABLE)?
+                .ip()
+                .into()
+        } else {
+            request.address.clone()
+        };
+        Ok(address)
+    }
+
+    /// Overrides the default `perform_handshake` method to pick the method
+    /// according to the server's [SelectionPolicy], among the ones with a
+    /// registered [Authenticator].
+    ///
+    /// # Errors
+    ///
+    /// Returns [ServerError::NoAcceptableAuthMethods] if the policy doesn't
+    /// accept any of the methods offered by the client.
+    async fn perform_handshake(&mut self) -> Result<AuthenticationMethod, super::ServerError> {
+        let settings = Arc::clone(&self.settings);
+        let available = settings.authenticators.methods();
+        let mut policy = settings.policy.restricted_to(&available);
+
+        // Clients authenticated by their certificate need no other method.
+        if self.context.identity != Identity::Anonymous {
+            policy = policy.preference().iter().fold(
+                SelectionPolicy::new([AuthenticationMethod::NO_AUTHENTICATION]),
+                |policy, method| policy.with_method(*method),
+            );
+        }
+
+        negotiate_method_impl(self, &policy).await
+    }
+
+    /// Overrides the default `authenticate` method to run the [Authenticator]
+    /// registered for the chosen method.
     ///
     /// # Errors
     ///
-    /// Returns [ServerError::AuthenticationFailed] if the client's credentials
-    /// do not match the server's credentials.
+    /// Returns [ServerError::AuthenticationFailed] if the authenticator
+    /// rejects the client.
     async fn authenticate(
         &mut self,
         method: AuthenticationMethod,
-    ) -> Result<(), super::ServerError> {
-        match method {
-            | AuthenticationMethod::USERNAME_PASSWORD => {
-                let auth_request =
-                    username_password::ClientAuthenticationRequest::read_from(self.stream())
-                        .await?;
-
-                if auth_request.username != self.credentials.0
-                    || auth_request.password != self.credentials.1
-                {
-                    return Err(ServerError::AuthenticationFailed);
-                }
+    ) -> Result<Identity, super::ServerError> {
+        if method == AuthenticationMethod::NO_AUTHENTICATION
+            && self.context.identity != Identity::Anonymous
+        {
+            return Ok(self.context.identity.clone());
+        }
 
-                Ok(())
-            },
-            | _ => default_authenticate_impl(method).await,
+        let settings = Arc::clone(&self.settings);
+        authenticate_with_impl(self, &settings.authenticators, method).await
+    }
+
+    /// Overrides the default `handle_request` method to reject the requests
+    /// the filter set with [Socks5Listener::with_request_filter] doesn't
+    /// accept with [Reply::CONNECTION_NOT_ALLOWED_BY_RULESET].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [ServerError] if the request is rejected or if its handler
+    /// fails.
+    async fn handle_request(mut self) -> Result<(), super::ServerError> {
+        let request = Request::read_from(self.stream()).await?;
+
+        if !self.settings.accepts(&self.context, &request) {
+            Response::NOT_ALLOWED_BY_RULESET
+                .write_to(self.stream())
+                .await?;
+            return Err(ServerError::RequestFailed(
+                Reply::CONNECTION_NOT_ALLOWED_BY_RULESET,
+            ));
         }
+
+        dispatch_request_impl(self, request).await
     }
 }
+
+/// Serves a SOCKS4 client admitted by [Settings::admits_socks4], filtering
+/// its request as the SOCKS5 request with the same command and target.
+///
+/// # Errors
+///
+/// Returns a [socks4::server::ServerError] if the request is rejected or if
+/// its handler fails.
+async fn serve_socks4(
+    stream: TcpStream,
+    context: &ConnectionContext,
+    settings: &Settings,
+) -> Result<(), socks4::server::ServerError> {
+    let mut server = Socks4Server::new(stream);
+    let request = socks4::proto::messages::Request::read_from(server.stream()).await?;
+
+    let command = match request.command {
+        | socks4::proto::CommandType::CONNECT => Some(CommandType::CONNECT),
+        | socks4::proto::CommandType::BIND => Some(CommandType::BIND),
+        | _ => None,
+    };
+    let accepted = command.is_none_or(|command| {
+        settings.accepts(context, &Request {
+            command,
+            address: request.address.clone().into(),
+            port: request.port,
+        })
+    });
+    if !accepted {
+        socks4::proto::messages::Response::REJECTED
+            .write_to(server.stream())
+            .await?;
+        return Err(socks4::server::ServerError::RequestFailed(
+            socks4::proto::Reply::REJECTED,
+        ));
+    }
+
+    socks4::server::dispatch_request_impl(server, request).await
+}
+
+/// Relays data between the client and the remote side until either direction
+/// is finished.
+pub(crate) async fn relay<S: futures::AsyncRead + futures::AsyncWrite + Unpin>(
+    client_stream: S,
+    remote_stream: TcpStream,
+) {
+    let (mut client_reader, mut client_writer) = tokio::io::split(client_stream.compat());
+    let (mut remote_reader, mut remote_writer) = tokio::io::split(remote_stream);
+    let client_to_remote = tokio::io::copy(&mut client_reader, &mut remote_writer);
+    let remote_to_client = tokio::io::copy(&mut remote_reader, &mut client_writer);
+
+    tokio::select! {
+        _ = client_to_remote => {},
+        _ = remote_to_client => {},
+    }
+}
+
+/// Returns the identity of a TLS client from its certificate,
+/// [Identity::Anonymous] if it has none, or `None` if the certificate is not
+/// mapped to an identity.
+#[cfg(feature = "tls")]
+fn certificate_identity(
+    stream: &TlsStream<TcpStream>,
+    mapper: Option<&CertificateMapper>,
+) -> Option<Identity> {
+    let Some(certificate) = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|certificates| certificates.first())
+    else {
+        return Some(Identity::Anonymous);
+    };
+
+    let certificate = ClientCertificate::parse(certificate)?;
+    match mapper {
+        | Some(mapper) => mapper(&certificate),
+        | None => {
+            certificate
+                .name()
+                .map(|name| Identity::Certificate(name.into()))
+        },
+    }
+}
+
+/// Returns the address the client connected to.
+///
+/// # Errors
+///
+/// Returns [ErrorKind::NotConnected] if the address is unknown.
+fn local_addr(context: &ConnectionContext) -> IoResult<SocketAddr> {
+    context
+        .local_addr
+        .ok_or_else(|| IoError::from(ErrorKind::NotConnected))
+}
+
+/// Resolves the destination of a relayed datagram or a resolve request.
+async fn resolve(address: &Address, port: u16) -> Option<SocketAddr> {
+    match *address {
+        | Address::Ipv4(addr) => Some((addr, port).into()),
+        | Address::Ipv6(addr) => Some((addr, port).into()),
+        | Address::Domain(ref domain) => {
+            let domain = std::str::from_utf8(domain).ok()?;
+            lookup_host((domain, port)).await.ok()?.next()
+        },
+    }
+}
+
+/// Waits until the deadline of an incomplete reassembly queue, or forever if
+/// there's none.
+async fn expiry(deadline: Option<Instant>) {
+    match deadline {
+        | Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        | None => std::future::pending().await,
+    }
+}
+
+/// Wraps a datagram received from a remote host into a UDP request header
+/// addressed to the client.
+///
+/// # Errors
+///
+/// Returns a [ConversionError] if the header can't be encoded, which doesn't
+/// happen with the IP address of the source.
+fn encapsulate(source: SocketAddr, payload: &[u8]) -> Result<Vec<u8>, ConversionError> {
+    let header = UdpHeader {
+        fragment: 0,
+        address:  source.ip().into(),
+        port:     source.port(),
+    };
+
+    let mut packet = Vec::with_capacity(payload.len().saturating_add(22));
+    header.encode_into(&mut packet)?;
+    packet.extend_from_slice(payload);
+    Ok(packet)
+}
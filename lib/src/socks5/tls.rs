@@ -0,0 +1,246 @@
+//! TLS support, backed by [rustls].
+//!
+//! TLS can either wrap whole connections, see
+//! [Socks5Listener::with_tls](crate::socks5::server::tokio::Socks5Listener::with_tls)
+//! and [Socks5Client::connect_tls](crate::socks5::client::tokio::Socks5Client::connect_tls),
+//! or be started after the handshake with the
+//! [AuthenticationMethod::IANA_SECURE_SOCKETS_LAYER](crate::socks5::proto::AuthenticationMethod::IANA_SECURE_SOCKETS_LAYER)
+//! method.
+//!
+//! With mutual TLS, the client certificates are verified against a set of
+//! certificate authorities and described by a [ClientCertificate], which
+//! servers map to the identity of the client.
+
+use std::{
+    fs::File,
+    io::{
+        BufReader,
+        Error as IoError,
+        ErrorKind,
+        Result as IoResult,
+    },
+    path::Path,
+    sync,
+};
+
+use tokio_rustls::rustls::{
+    ClientConfig,
+    RootCertStore,
+    ServerConfig,
+    crypto::{
+        CryptoProvider,
+        ring,
+    },
+    pki_types::{
+        CertificateDer,
+        PrivateKeyDer,
+    },
+    server::WebPkiClientVerifier,
+};
+pub use tokio_rustls::{
+    TlsAcceptor as Acceptor,
+    TlsConnector as Connector,
+    rustls,
+};
+
+/// Reads all certificates from a PEM file.
+///
+/// # Errors
+///
+/// Returns an I/O error if the file can't be read, is malformed or holds no
+/// certificates.
+pub fn load_certificates<P: AsRef<Path>>(path: P) -> IoResult<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certificates = rustls_pemfile::certs(&mut reader).collect::<IoResult<Vec<_>>>()?;
+    if certificates.is_empty() {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            "no certificates found",
+        ));
+    }
+    Ok(certificates)
+}
+
+/// Reads the first private key from a PEM file.
+///
+/// # Errors
+///
+/// Returns an I/O error if the file can't be read, is malformed or holds no
+/// private key.
+pub fn load_private_key<P: AsRef<Path>>(path: P) -> IoResult<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidData, "no private key found"))
+}
+
+/// Builds an acceptor for the server side from the PEM files of the
+/// certificate chain and its private key.
+///
+/// # Errors
+///
+/// Returns an I/O error if the files can't be loaded or don't match.
+pub fn acceptor<C: AsRef<Path>, K: AsRef<Path>>(
+    certificates: C,
+    private_key: K,
+) -> IoResult<Acceptor> {
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(IoError::other)?
+        .with_no_client_auth()
+        .with_single_cert(
+            load_certificates(certificates)?,
+            load_private_key(private_key)?,
+        )
+        .map_err(IoError::other)?;
+    Ok(Acceptor::from(sync::Arc::new(config)))
+}
+
+/// Builds an acceptor for the server side which requires the clients to
+/// present a certificate issued by one of the certificate authorities from
+/// the PEM file.
+///
+/// # Errors
+///
+/// Returns an I/O error if the files can't be loaded or don't match.
+pub fn acceptor_with_client_auth<C: AsRef<Path>, K: AsRef<Path>, A: AsRef<Path>>(
+    certificates: C,
+    private_key: K,
+    authorities: A,
+) -> IoResult<Acceptor> {
+    let verifier = WebPkiClientVerifier::builder_with_provider(
+        sync::Arc::new(roots(authorities)?),
+        provider(),
+    )
+    .build()
+    .map_err(IoError::other)?;
+    let config = ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(IoError::other)?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            load_certificates(certificates)?,
+            load_private_key(private_key)?,
+        )
+        .map_err(IoError::other)?;
+    Ok(Acceptor::from(sync::Arc::new(config)))
+}
+
+/// Builds a connector for the client side, trusting the certificate
+/// authorities from the PEM file.
+///
+/// # Errors
+///
+/// Returns an I/O error if the file can't be loaded or holds an invalid
+/// certificate.
+pub fn connector<P: AsRef<Path>>(authorities: P) -> IoResult<Connector> {
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(IoError::other)?
+        .with_root_certificates(roots(authorities)?)
+        .with_no_client_auth();
+    Ok(Connector::from(sync::Arc::new(config)))
+}
+
+/// Builds a connector for the client side which presents the certificate
+/// chain from the PEM file, for servers requiring mutual TLS.
+///
+/// # Errors
+///
+/// Returns an I/O error if the files can't be loaded or don't match.
+pub fn connector_with_client_auth<A: AsRef<Path>, C: AsRef<Path>, K: AsRef<Path>>(
+    authorities: A,
+    certificates: C,
+    private_key: K,
+) -> IoResult<Connector> {
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()
+        .map_err(IoError::other)?
+        .with_root_certificates(roots(authorities)?)
+        .with_client_auth_cert(
+            load_certificates(certificates)?,
+            load_private_key(private_key)?,
+        )
+        .map_err(IoError::other)?;
+    Ok(Connector::from(sync::Arc::new(config)))
+}
+
+/// What a verified client certificate says about the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// The subject, e.g. `CN=backup,O=Example`.
+    pub subject:     Box<str>,
+    /// The common name of the subject, if any.
+    pub common_name: Option<Box<str>>,
+    /// The DNS names, e-mail addresses and URIs of the subject alternative
+    /// name extension.
+    pub alt_names:   Box<[Box<str>]>,
+}
+
+impl ClientCertificate {
+    /// Describes a DER-encoded certificate, or returns `None` if it's
+    /// malformed.
+    pub fn parse(certificate: &[u8]) -> Option<Self> {
+        use x509_parser::extensions::GeneralName;
+
+        let (_, certificate) = x509_parser::parse_x509_certificate(certificate).ok()?;
+        let subject = certificate.subject();
+        let common_name = subject
+            .iter_common_name()
+            .next()
+            .and_then(|name| name.as_str().ok())
+            .map(Box::from);
+        let alt_names = certificate
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|extension| {
+                extension
+                    .value
+                    .general_names
+                    .iter()
+                    .filter_map(|name| {
+                        match *name {
+                            | GeneralName::DNSName(name)
+                            | GeneralName::RFC822Name(name)
+                            | GeneralName::URI(name) => Some(Box::from(name)),
+                            | _ => None,
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            subject: subject.to_string().into(),
+            common_name,
+            alt_names,
+        })
+    }
+
+    /// Returns the name identifying the client: the first subject
+    /// alternative name, or the common name.
+    pub fn name(&self) -> Option<&str> {
+        self.alt_names
+            .first()
+            .or(self.common_name.as_ref())
+            .map(|name| &**name)
+    }
+}
+
+/// Reads the certificate authorities to trust from a PEM file.
+///
+/// # Errors
+///
+/// Returns an I/O error if the file can't be loaded or holds an invalid
+/// certificate.
+fn roots<P: AsRef<Path>>(authorities: P) -> IoResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for certificate in load_certificates(authorities)? {
+        roots.add(certificate).map_err(IoError::other)?;
+    }
+    Ok(roots)
+}
+
+fn provider() -> sync::Arc<CryptoProvider> {
+    sync::Arc::new(ring::default_provider())
+}
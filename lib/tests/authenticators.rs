@@ -0,0 +1,141 @@
+mod common;
+
+use std::io::Result as IoResult;
+
+use common::credentials;
+use futures::{
+    AsyncReadExt,
+    AsyncWriteExt,
+    future::BoxFuture,
+};
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::AuthenticationMethod,
+    server::{
+        BoxedStream,
+        ConnectionContext,
+        ServerError,
+        auth::{
+            Authenticator,
+            Identity,
+            NoAuthentication,
+        },
+        tokio::Socks5Listener,
+    },
+};
+use tokio::net::TcpStream;
+
+/// A method from the private-use range.
+fn knock_method() -> AuthenticationMethod {
+    AuthenticationMethod::from(0x80)
+}
+
+/// Accepts the clients sending the right byte, and replies with a status
+/// byte.
+struct Knock(u8);
+
+impl Authenticator<BoxedStream> for Knock {
+    fn method(&self) -> AuthenticationMethod {
+        knock_method()
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        stream: &'a mut BoxedStream,
+        _: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async move {
+            let mut knock = [0; 1];
+            stream.read_exact(&mut knock).await?;
+            if knock != [self.0] {
+                stream.write_all(&[0x01]).await?;
+                stream.close().await?;
+                return Err(ServerError::AuthenticationFailed);
+            }
+            stream.write_all(&[0x00]).await?;
+            Ok(Identity::User(b"knocker".as_slice().into()))
+        })
+    }
+}
+
+async fn handshake(
+    listener: Socks5Listener,
+    offered: &[AuthenticationMethod],
+) -> Result<(Socks5Client, AuthenticationMethod), ClientError> {
+    let proxy = common::serve(listener)?;
+    let stream = TcpStream::connect(proxy).await?;
+    let mut client = Socks5Client::new(stream, credentials("user", "secret"));
+    let choice = client.perform_handshake(offered.into()).await?;
+    Ok((client, choice))
+}
+
+async fn send_knock(client: &mut Socks5Client, byte: u8) -> IoResult<u8> {
+    let stream = client.stream();
+    stream.write_all(&[byte]).await?;
+    let mut status = [0; 1];
+    stream.read_exact(&mut status).await?;
+    Ok(status[0])
+}
+
+#[tokio::test]
+async fn listener_without_credentials_refuses_everyone() -> Result<(), ClientError> {
+    let listener = Socks5Listener::bind_without_credentials("127.0.0.1:0").await?;
+    let (_, choice) = handshake(listener, &[
+        AuthenticationMethod::NO_AUTHENTICATION,
+        AuthenticationMethod::USERNAME_PASSWORD,
+    ])
+    .await?;
+    assert_eq!(choice, AuthenticationMethod::NO_ACCEPTABLE_METHODS);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn custom_authenticator_is_run() -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let listener = || {
+        async {
+            let listener = Socks5Listener::bind_without_credentials("127.0.0.1:0").await?;
+            IoResult::Ok(listener.with_authenticator(Knock(b'k')))
+        }
+    };
+
+    let (mut client, choice) = handshake(listener().await?, &[
+        AuthenticationMethod::USERNAME_PASSWORD,
+        knock_method(),
+    ])
+    .await?;
+    assert_eq!(
+        choice,
+        knock_method(),
+        "the registered method should be picked"
+    );
+    assert_eq!(send_knock(&mut client, b'k').await?, 0x00);
+    client
+        .send_connect_request(echo.ip().into(), echo.port())
+        .await?;
+
+    let (mut client, _) = handshake(listener().await?, &[knock_method()]).await?;
+    assert_eq!(send_knock(&mut client, b'x').await?, 0x01);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn registering_no_authentication_lifts_the_requirement() -> Result<(), ClientError> {
+    let listener = common::listener().await?;
+    let (_, choice) = handshake(listener, &[AuthenticationMethod::NO_AUTHENTICATION]).await?;
+    assert_eq!(choice, AuthenticationMethod::NO_ACCEPTABLE_METHODS);
+
+    let listener = common::listener()
+        .await?
+        .with_authenticator(NoAuthentication);
+    let (_, choice) = handshake(listener, &[AuthenticationMethod::NO_AUTHENTICATION]).await?;
+    assert_eq!(choice, AuthenticationMethod::NO_AUTHENTICATION);
+
+    Ok(())
+}
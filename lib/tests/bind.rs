@@ -0,0 +1,172 @@
+mod common;
+
+use std::net::Ipv4Addr;
+
+use common::credentials;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::{
+        Address,
+        Reply,
+    },
+};
+use tokio::{
+    io::{
+        AsyncReadExt,
+        AsyncWriteExt,
+        DuplexStream,
+    },
+    net::TcpStream,
+};
+use tokio_util::compat::FuturesAsyncReadCompatExt;
+
+/// Answers a BIND request on the stream with the given replies, without
+/// authentication.
+async fn fake_proxy(mut stream: DuplexStream, replies: &[&[u8]]) -> std::io::Result<()> {
+    let mut greeting = [0; 4];
+    stream.read_exact(&mut greeting).await?;
+    stream.write_all(&[0x05, 0x00]).await?;
+
+    let mut request = [0; 10];
+    stream.read_exact(&mut request).await?;
+    assert_eq!(
+        request.get(.. 4),
+        Some([0x05, 0x02, 0x00, 0x01].as_slice()),
+        "client should send a BIND request"
+    );
+
+    for reply in replies {
+        stream.write_all(reply).await?;
+    }
+    stream.write_all(b"ping").await
+}
+
+#[tokio::test]
+async fn inbound_peer_is_relayed() -> Result<(), ClientError> {
+    let proxy = common::serve(common::listener().await?)?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let handle = Socks5Client::new(stream, credentials("user", "secret"))
+        .bind_to_target(Ipv4Addr::LOCALHOST.into(), 0)
+        .await?;
+    assert_eq!(
+        handle.address(),
+        &Address::from(proxy.ip()),
+        "proxy should listen on the address the client connected to"
+    );
+
+    let mut peer = TcpStream::connect((proxy.ip(), handle.port())).await?;
+    let (stream, _, peer_port) = handle.accept().await?;
+    assert_eq!(
+        peer_port,
+        peer.local_addr()?.port(),
+        "peer should be reported"
+    );
+
+    let mut stream = stream.compat();
+    peer.write_all(b"ping").await?;
+    let mut buffer = [0; 4];
+    stream.read_exact(&mut buffer).await?;
+    assert_eq!(&buffer, b"ping", "data should be relayed to the client");
+
+    stream.write_all(b"pong").await?;
+    peer.read_exact(&mut buffer).await?;
+    assert_eq!(&buffer, b"pong", "data should be relayed to the peer");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unexpected_peer_is_rejected() -> Result<(), ClientError> {
+    let proxy = common::serve(common::listener().await?)?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let handle = Socks5Client::new(stream, credentials("user", "secret"))
+        .bind_to_target(Ipv4Addr::new(192, 0, 2, 1).into(), 0)
+        .await?;
+
+    let _peer = TcpStream::connect((proxy.ip(), handle.port())).await?;
+    let result = handle.accept().await;
+    assert!(
+        matches!(
+            result,
+            Err(ClientError::RequestFailed(
+                Reply::CONNECTION_NOT_ALLOWED_BY_RULESET
+            ))
+        ),
+        "peer should be rejected: {result:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn handle_reports_both_replies() -> Result<(), ClientError> {
+    let (client, proxy) = tokio::io::duplex(64);
+    let proxy = tokio::spawn(fake_proxy(proxy, &[
+        &[0x05, 0x00, 0x00, 0x01, 192, 0, 2, 7, 0x04, 0x38],
+        &[
+            0x05, 0x00, 0x00, 0x03, 4, b'p', b'e', b'e', b'r', 0x10, 0x92,
+        ],
+    ]));
+
+    let handle = Socks5Client::from_stream(client, credentials("", ""))
+        .bind_to_target(Ipv4Addr::UNSPECIFIED.into(), 0)
+        .await?;
+    assert_eq!(
+        handle.address(),
+        &Address::from(Ipv4Addr::new(192, 0, 2, 7)),
+        "first reply should carry the listening address"
+    );
+    assert_eq!(
+        handle.port(),
+        1080,
+        "first reply should carry the listening port"
+    );
+
+    let (stream, address, port) = handle.accept().await?;
+    assert_eq!(
+        address,
+        Address::from("peer"),
+        "second reply should carry the peer address"
+    );
+    assert_eq!(port, 4242, "second reply should carry the peer port");
+
+    let mut stream = stream.compat();
+    let mut buffer = [0; 4];
+    stream.read_exact(&mut buffer).await?;
+    assert_eq!(
+        &buffer, b"ping",
+        "stream should be handed over after the second reply"
+    );
+
+    proxy.await.map_err(std::io::Error::from)??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_second_reply_is_an_error() -> Result<(), ClientError> {
+    let (client, proxy) = tokio::io::duplex(64);
+    tokio::spawn(fake_proxy(proxy, &[
+        &[0x05, 0x00, 0x00, 0x01, 192, 0, 2, 7, 0x04, 0x38],
+        &[0x05, 0x01, 0x00, 0x01, 0, 0, 0, 0, 0, 0],
+    ]));
+
+    let handle = Socks5Client::from_stream(client, credentials("", ""))
+        .bind_to_target(Ipv4Addr::UNSPECIFIED.into(), 0)
+        .await?;
+    let result = handle.accept().await;
+    assert!(
+        matches!(
+            result,
+            Err(ClientError::RequestFailed(Reply::GENERAL_FAILURE))
+        ),
+        "failure should be reported: {result:?}"
+    );
+
+    Ok(())
+}
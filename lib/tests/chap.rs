@@ -0,0 +1,95 @@
+mod common;
+
+use std::{
+    io::Result as IoResult,
+    net::SocketAddr,
+};
+
+use common::credentials;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        chap_auth_impl,
+        tokio::Socks5Client,
+    },
+    proto::{
+        AuthenticationMethod,
+        messages::auth::chap::Algorithm,
+    },
+    server::{
+        auth::ChallengeHandshake,
+        tokio::Socks5Listener,
+    },
+};
+use tokio::net::TcpStream;
+
+async fn spawn_proxy(authenticator: ChallengeHandshake) -> IoResult<SocketAddr> {
+    let listener = Socks5Listener::bind_without_credentials("127.0.0.1:0")
+        .await?
+        .with_authenticator(authenticator);
+    common::serve(listener)
+}
+
+async fn connect(proxy: SocketAddr, algorithms: &[Algorithm]) -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let stream = TcpStream::connect(proxy).await?;
+    let mut stream = Socks5Client::new(stream, credentials("user", "secret"))
+        .with_challenge_handshake(algorithms)
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn valid_responses_are_accepted() -> Result<(), ClientError> {
+    let proxy = spawn_proxy(ChallengeHandshake::new(credentials("user", "secret"))).await?;
+    connect(proxy, &[Algorithm::HMAC_MD5]).await
+}
+
+#[tokio::test]
+async fn non_standard_algorithm_is_opt_in() -> Result<(), ClientError> {
+    let proxy = spawn_proxy(ChallengeHandshake::new(credentials("user", "secret"))).await?;
+    let result = connect(proxy, &[Algorithm::NON_STANDARD_HMAC_SHA256]).await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "non-standard algorithm should not be accepted by default: {result:?}"
+    );
+
+    let authenticator = ChallengeHandshake::new(credentials("user", "secret"))
+        .with_algorithms([Algorithm::NON_STANDARD_HMAC_SHA256]);
+    let proxy = spawn_proxy(authenticator).await?;
+    connect(proxy, &[Algorithm::NON_STANDARD_HMAC_SHA256]).await
+}
+
+#[tokio::test]
+async fn invalid_responses_are_rejected() -> Result<(), ClientError> {
+    let proxy = spawn_proxy(ChallengeHandshake::new(credentials("user", "secret"))).await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let mut client = Socks5Client::new(stream, credentials("user", "wrong"));
+    let choice = client
+        .perform_handshake([AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE].into())
+        .await?;
+    assert_eq!(
+        choice,
+        AuthenticationMethod::IANA_CHALLENGE_HANDSHAKE,
+        "server should pick the challenge-handshake method"
+    );
+
+    let result = chap_auth_impl(&mut client, b"user".as_slice().into(), b"wrong", &[
+        Algorithm::HMAC_MD5,
+    ])
+    .await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {result:?}"
+    );
+
+    let mut buffer = [0; 1];
+    let read = futures::AsyncReadExt::read(client.stream(), &mut buffer).await?;
+    assert_eq!(read, 0, "server should close the connection");
+
+    Ok(())
+}
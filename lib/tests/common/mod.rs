@@ -0,0 +1,85 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code, reason = "each test uses a part of the helpers")]
+
+use std::{
+    io::Result as IoResult,
+    net::SocketAddr,
+    path::PathBuf,
+    sync,
+};
+
+use socker::socks5::server::tokio::Socks5Listener;
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncReadExt,
+        AsyncWrite,
+        AsyncWriteExt,
+    },
+    net::TcpListener,
+};
+
+pub type Credentials = sync::Arc<(Box<[u8]>, Box<[u8]>)>;
+
+pub fn credentials(username: &str, password: &str) -> Credentials {
+    sync::Arc::new((username.as_bytes().into(), password.as_bytes().into()))
+}
+
+/// Returns the path of a file from `tests/data`.
+pub fn data(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/data")
+        .join(name)
+}
+
+/// Binds a listener on the loopback address, accepting `user:secret`.
+///
+/// # Errors
+///
+/// Returns an I/O error if the listener can't be bound.
+pub async fn listener() -> IoResult<Socks5Listener> {
+    Socks5Listener::bind("127.0.0.1:0", credentials("user", "secret")).await
+}
+
+/// Runs the listener in the background and returns its address.
+///
+/// # Errors
+///
+/// Returns an I/O error if the address can't be retrieved.
+pub fn serve(listener: Socks5Listener) -> IoResult<SocketAddr> {
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move { listener.run().await });
+    Ok(addr)
+}
+
+/// Spawns a server echoing the first four bytes of a single connection.
+///
+/// # Errors
+///
+/// Returns an I/O error if the server can't be bound.
+pub async fn spawn_echo() -> IoResult<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let mut buffer = [0; 4];
+        stream.read_exact(&mut buffer).await?;
+        stream.write_all(&buffer).await
+    });
+    Ok(addr)
+}
+
+/// Checks that the stream is relayed to a server from [spawn_echo].
+///
+/// # Errors
+///
+/// Returns an I/O error if the exchange fails.
+pub async fn ping<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S) -> IoResult<()> {
+    stream.write_all(b"ping").await?;
+    stream.flush().await?;
+    let mut buffer = [0; 4];
+    stream.read_exact(&mut buffer).await?;
+    assert_eq!(&buffer, b"ping", "data should be relayed");
+    Ok(())
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use std::{
+    io::{
+        Error as IoError,
+        ErrorKind,
+    },
+    net::SocketAddr,
+};
+
+use common::credentials;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::{
+        AuthenticationMethod,
+        Reply,
+    },
+    server::{
+        ConnectionContext,
+        auth::Identity,
+    },
+};
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{
+        self,
+        UnboundedReceiver,
+    },
+};
+
+/// Spawns a proxy reporting the context of each request, and only serving
+/// the ones to the given port.
+async fn spawn_proxy(
+    allowed_port: u16,
+) -> Result<(SocketAddr, UnboundedReceiver<ConnectionContext>), ClientError> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let listener = common::listener()
+        .await?
+        .with_request_filter(move |context, request| {
+            let _ = sender.send(context.clone());
+            request.port == allowed_port
+        });
+    Ok((common::serve(listener)?, receiver))
+}
+
+#[tokio::test]
+async fn context_reaches_the_filter() -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let (proxy, mut contexts) = spawn_proxy(echo.port()).await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let client_addr = stream.local_addr()?;
+    let mut stream = Socks5Client::new(stream, credentials("user", "secret"))
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+
+    let context = contexts
+        .recv()
+        .await
+        .ok_or_else(|| IoError::from(ErrorKind::UnexpectedEof))?;
+    assert_eq!(
+        context.identity,
+        Identity::User(b"user".as_slice().into()),
+        "identity should be the authenticated user"
+    );
+    assert_eq!(
+        context.method,
+        Some(AuthenticationMethod::USERNAME_PASSWORD),
+        "method should be the negotiated one"
+    );
+    assert_eq!(
+        context.peer_addr,
+        Some(client_addr),
+        "peer should be the client"
+    );
+    assert_eq!(
+        context.local_addr,
+        Some(proxy),
+        "local address should be the proxy"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn filtered_request_is_rejected() -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let (proxy, _contexts) = spawn_proxy(echo.port().wrapping_add(1)).await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let result = Socks5Client::new(stream, credentials("user", "secret"))
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await;
+    assert!(
+        matches!(
+            result,
+            Err(ClientError::RequestFailed(
+                Reply::CONNECTION_NOT_ALLOWED_BY_RULESET
+            ))
+        ),
+        "request should be rejected: {result:?}"
+    );
+
+    Ok(())
+}
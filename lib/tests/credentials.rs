@@ -0,0 +1,170 @@
+mod common;
+
+use common::credentials;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    server::{
+        auth::UsernamePassword,
+        credentials::{
+            CredentialStore,
+            HtpasswdError,
+            HtpasswdStore,
+            MemoryStore,
+        },
+        tokio::Socks5Listener,
+    },
+};
+use tokio::net::TcpStream;
+
+/// One user per supported scheme, whose password is the name of the scheme.
+const HTPASSWD: &str = "\
+# Users of the proxy.
+
+argon:$argon2id$v=19$m=64,t=1,p=1$c29ja2Vyc2FsdA$mHdzEe3UkIWyh4iLOoZld1b7/nSeSC0es5TnM1YuyZw
+bcrypt:$2y$04$socksersaltsocksersaleU8mfIS8fABEvVRfd8Wdktg.FPHXdzNu\r
+sha256:$5$rounds=1000$sockersalt$WQqmjx5jlvsnkOIR3o.KcEIgqf699V.mn2NM35vCXm3
+sha512:$6$rounds=1000$sockersalt$qJZCCEwaZ7KDxYVLkeiqy/Tf4vdzcVjYdyOunWsujM5.\
+                        Nsdb0NMTdY6HvSHhPRKFL03lEAMc120w2Qc8pkLaf1
+";
+
+const SCHEMES: [&str; 4] = ["argon", "bcrypt", "sha256", "sha512"];
+
+#[test]
+fn each_scheme_is_verified() -> Result<(), HtpasswdError> {
+    let store = HtpasswdStore::parse(HTPASSWD)?;
+    for scheme in SCHEMES {
+        assert!(
+            store.verify(scheme.as_bytes(), scheme.as_bytes()),
+            "{scheme} password should be accepted"
+        );
+        assert!(
+            !store.verify(scheme.as_bytes(), b"wrong"),
+            "wrong {scheme} password should be rejected"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn unknown_user_is_rejected() -> Result<(), HtpasswdError> {
+    let store = HtpasswdStore::parse(HTPASSWD)?;
+    assert!(
+        !store.verify(b"nobody", b"argon"),
+        "unknown user should be rejected"
+    );
+    assert!(
+        !store.verify(b"# Users of the proxy.", b""),
+        "comments should not be users"
+    );
+
+    let empty = HtpasswdStore::parse("# No users yet.\n")?;
+    assert!(
+        !empty.verify(b"argon", b"argon"),
+        "empty store should reject everyone"
+    );
+    Ok(())
+}
+
+#[test]
+fn bad_lines_are_reported() {
+    let result = HtpasswdStore::parse("# Users\n\nuser\n");
+    assert!(
+        matches!(result, Err(HtpasswdError::MalformedLine(3))),
+        "line without a hash should be reported: {result:?}"
+    );
+
+    let result = HtpasswdStore::parse(":$5$rounds=1000$salt$hash\n");
+    assert!(
+        matches!(result, Err(HtpasswdError::MalformedLine(1))),
+        "line without a username should be reported: {result:?}"
+    );
+
+    let result = HtpasswdStore::parse("user:$1$salt$hash\n");
+    assert!(
+        matches!(result, Err(HtpasswdError::UnsupportedHash(1))),
+        "MD5-crypt should not be supported: {result:?}"
+    );
+
+    let result = HtpasswdStore::parse("user:plaintext\n");
+    assert!(
+        matches!(result, Err(HtpasswdError::UnsupportedHash(1))),
+        "plaintext should not be supported: {result:?}"
+    );
+
+    let result = HtpasswdStore::parse("user:$argon2id$garbage\n");
+    assert!(
+        matches!(result, Err(HtpasswdError::UnsupportedHash(1))),
+        "malformed Argon2 hash should be reported: {result:?}"
+    );
+}
+
+#[test]
+fn memory_store_is_verified() {
+    let mut store: MemoryStore = [("user", "secret"), ("other", "password")]
+        .into_iter()
+        .collect();
+    assert!(
+        store.verify(b"user", b"secret"),
+        "password should be accepted"
+    );
+    assert!(
+        !store.verify(b"user", b"password"),
+        "wrong password should be rejected"
+    );
+    assert!(
+        !store.verify(b"nobody", b"secret"),
+        "unknown user should be rejected"
+    );
+    assert!(
+        !store.verify(b"nobody", b""),
+        "unknown user should be rejected"
+    );
+
+    store.insert("user", "changed");
+    assert!(
+        !store.verify(b"user", b"secret"),
+        "old password should be rejected"
+    );
+    assert!(
+        store.verify(b"user", b"changed"),
+        "new password should be accepted"
+    );
+
+    store.remove(b"user");
+    assert!(
+        !store.verify(b"user", b"changed"),
+        "removed user should be rejected"
+    );
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn htpasswd_store_serves_clients() -> Result<(), ClientError> {
+    let store = HtpasswdStore::parse(HTPASSWD)
+        .map_err(|error| std::io::Error::other(format!("{error:?}")))?;
+    let listener = Socks5Listener::bind_without_credentials("127.0.0.1:0")
+        .await?
+        .with_authenticator(UsernamePassword::new(store));
+    let proxy = common::serve(listener)?;
+
+    let echo = common::spawn_echo().await?;
+    let stream = TcpStream::connect(proxy).await?;
+    let mut stream = Socks5Client::new(stream, credentials("argon", "argon"))
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let result = Socks5Client::new(stream, credentials("argon", "wrong"))
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {result:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    io::Result as IoResult,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    time::Duration,
+};
+
+use common::{
+    Credentials,
+    credentials,
+};
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    server::{
+        auth::ExternalCommand,
+        tokio::Socks5Listener,
+    },
+};
+use tokio::net::TcpStream;
+
+/// Accepts `user:secret` from the loopback address.
+const SCRIPT: &str = r#"
+read -r username
+read -r password
+[ "$username" = user ] && [ "$password" = secret ] &&
+    [ "$SOCKS_USERNAME" = user ] && [ "${SOCKS_PEER_ADDR%:*}" = 127.0.0.1 ]
+"#;
+
+/// Fails if another instance holds the lock directory given as argument.
+const EXCLUSIVE_SCRIPT: &str = r#"
+mkdir "$1" || exit 1
+sleep 0.3
+rmdir "$1"
+"#;
+
+/// Accepts the clients if the `CARGO_PKG_NAME` variable, set by Cargo, is
+/// inherited.
+const ENV_SCRIPT: &str = r#"[ -n "${CARGO_PKG_NAME+set}" ]"#;
+
+async fn spawn_proxy(command: ExternalCommand) -> IoResult<SocketAddr> {
+    let listener = Socks5Listener::bind_without_credentials("127.0.0.1:0")
+        .await?
+        .with_authenticator(command);
+    common::serve(listener)
+}
+
+async fn connect(proxy: SocketAddr, creds: Credentials) -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let stream = TcpStream::connect(proxy).await?;
+    let mut stream = Socks5Client::new(stream, creds)
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+    Ok(())
+}
+
+fn assert_rejected(result: Result<(), ClientError>) {
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {result:?}"
+    );
+}
+
+#[tokio::test]
+async fn exit_status_is_the_verdict() -> Result<(), ClientError> {
+    let command = ExternalCommand::new("/bin/sh").with_args(["-c", SCRIPT]);
+    let proxy = spawn_proxy(command).await?;
+
+    connect(proxy, credentials("user", "secret")).await?;
+    assert_rejected(connect(proxy, credentials("user", "wrong")).await);
+    assert_rejected(connect(proxy, credentials("user", "secret\nuser")).await);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn slow_command_is_rejected() -> Result<(), ClientError> {
+    let command = ExternalCommand::new("/bin/sh")
+        .with_args(["-c", "sleep 10"])
+        .with_timeout(Duration::from_millis(200))
+        .with_concurrency_limit(NonZeroUsize::MIN);
+    let proxy = spawn_proxy(command).await?;
+
+    assert_rejected(connect(proxy, credentials("user", "secret")).await);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn concurrent_commands_are_limited() -> Result<(), ClientError> {
+    let lock = std::env::temp_dir().join(format!("socker-{}-lock", std::process::id()));
+    let command = ExternalCommand::new("/bin/sh")
+        .with_args([
+            "-c".as_ref(),
+            EXCLUSIVE_SCRIPT.as_ref(),
+            "sh".as_ref(),
+            lock.as_os_str(),
+        ])
+        .with_concurrency_limit(NonZeroUsize::MIN);
+    let proxy = spawn_proxy(command).await?;
+
+    let (first, second) = tokio::join!(
+        connect(proxy, credentials("user", "secret")),
+        connect(proxy, credentials("user", "secret")),
+    );
+    first?;
+    second
+}
+
+#[tokio::test]
+async fn environment_can_be_restricted() -> Result<(), ClientError> {
+    let inherited = ExternalCommand::new("/bin/sh").with_args(["-c", ENV_SCRIPT]);
+    let proxy = spawn_proxy(inherited).await?;
+    connect(proxy, credentials("user", "secret")).await?;
+
+    let restricted = ExternalCommand::new("/bin/sh")
+        .with_args(["-c", ENV_SCRIPT])
+        .with_inherited_env(["PATH"]);
+    let proxy = spawn_proxy(restricted).await?;
+    assert_rejected(connect(proxy, credentials("user", "secret")).await);
+
+    let allowed = ExternalCommand::new("/bin/sh")
+        .with_args(["-c", ENV_SCRIPT])
+        .with_inherited_env(["CARGO_PKG_NAME"]);
+    let proxy = spawn_proxy(allowed).await?;
+    connect(proxy, credentials("user", "secret")).await
+}
@@ -0,0 +1,170 @@
+mod common;
+
+use std::{
+    io::Result as IoResult,
+    net::SocketAddr,
+    sync,
+};
+
+use futures::{
+    AsyncReadExt as _,
+    AsyncWriteExt as _,
+};
+use socker::socks5::{
+    client::{
+        ClientError,
+        tokio::Socks5Client,
+    },
+    gssapi::{
+        EncapsulatedStream,
+        Mechanism,
+        MechanismError,
+        SecurityContext,
+    },
+    proto::messages::auth::gssapi::ProtectionLevel,
+    server::{
+        auth::Gssapi,
+        tokio::Socks5Listener,
+    },
+};
+use tokio::net::TcpStream;
+use tokio_util::compat::Compat;
+
+const XOR_KEY: u8 = 0x5A;
+
+/// A mechanism exchanging fixed tokens and "encrypting" with a XOR.
+struct FakeMechanism {
+    token: &'static [u8],
+}
+
+impl Mechanism for FakeMechanism {
+    fn accept(&self) -> Result<Box<dyn SecurityContext>, MechanismError> {
+        Ok(Box::new(FakeContext {
+            initiator:   false,
+            token:       self.token,
+            established: false,
+        }))
+    }
+
+    fn initiate(&self, _: &str) -> Result<Box<dyn SecurityContext>, MechanismError> {
+        Ok(Box::new(FakeContext {
+            initiator:   true,
+            token:       self.token,
+            established: false,
+        }))
+    }
+}
+
+struct FakeContext {
+    initiator:   bool,
+    token:       &'static [u8],
+    established: bool,
+}
+
+impl SecurityContext for FakeContext {
+    fn step(&mut self, token: Option<&[u8]>) -> Result<Option<Box<[u8]>>, MechanismError> {
+        match (self.initiator, token) {
+            | (true, None) => Ok(Some(self.token.into())),
+            | (true, Some(b"welcome")) | (false, Some(b"hello")) => {
+                self.established = true;
+                Ok((!self.initiator).then(|| b"welcome".as_slice().into()))
+            },
+            | _ => Err(MechanismError("unexpected token".into())),
+        }
+    }
+
+    fn is_established(&self) -> bool {
+        self.established
+    }
+
+    fn wrap(&mut self, message: &[u8], confidential: bool) -> Result<Box<[u8]>, MechanismError> {
+        let mask = if confidential {
+            XOR_KEY
+        } else {
+            0
+        };
+        Ok([mask]
+            .into_iter()
+            .chain(message.iter().map(|byte| byte ^ mask))
+            .collect())
+    }
+
+    fn unwrap(&mut self, token: &[u8]) -> Result<Box<[u8]>, MechanismError> {
+        let Some((mask, message)) = token.split_first() else {
+            return Err(MechanismError("empty token".into()));
+        };
+        Ok(message.iter().map(|byte| byte ^ mask).collect())
+    }
+
+    fn peer_name(&self) -> Option<Box<[u8]>> {
+        Some(b"client@EXAMPLE.COM".as_slice().into())
+    }
+}
+
+async fn spawn_proxy() -> IoResult<SocketAddr> {
+    let listener = Socks5Listener::bind_without_credentials("127.0.0.1:0")
+        .await?
+        .with_authenticator(Gssapi::new(FakeMechanism {
+            token: b"hello"
+        }));
+    common::serve(listener)
+}
+
+async fn connect(
+    proxy: SocketAddr,
+    echo: SocketAddr,
+    token: &'static [u8],
+    level: ProtectionLevel,
+) -> Result<EncapsulatedStream<Compat<TcpStream>>, ClientError> {
+    let stream = TcpStream::connect(proxy).await?;
+    let credentials = sync::Arc::new((Box::default(), Box::default()));
+    Socks5Client::new(stream, credentials)
+        .connect_with_gssapi(
+            &FakeMechanism {
+                token,
+            },
+            "rcmd@localhost",
+            &[level],
+            echo.ip().into(),
+            echo.port(),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn data_is_encapsulated() -> Result<(), ClientError> {
+    for level in [ProtectionLevel::INTEGRITY, ProtectionLevel::CONFIDENTIALITY] {
+        let proxy = spawn_proxy().await?;
+        let echo = common::spawn_echo().await?;
+
+        let mut stream = connect(proxy, echo, b"hello", level).await?;
+        assert_eq!(
+            stream.session().level(),
+            level,
+            "requested level should be accepted"
+        );
+
+        stream.write_all(b"ping").await?;
+        stream.flush().await?;
+        let mut buffer = [0; 4];
+        stream.read_exact(&mut buffer).await?;
+        assert_eq!(&buffer, b"ping", "data should be relayed");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejected_token_fails() -> Result<(), ClientError> {
+    let proxy = spawn_proxy().await?;
+    let echo = common::spawn_echo().await?;
+
+    let result = connect(proxy, echo, b"forged", ProtectionLevel::INTEGRITY).await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {:?}",
+        result.err()
+    );
+
+    Ok(())
+}
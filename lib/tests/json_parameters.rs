@@ -0,0 +1,129 @@
+mod common;
+
+use std::{
+    io::{
+        Error as IoError,
+        ErrorKind,
+        Result as IoResult,
+    },
+    net::SocketAddr,
+    sync,
+};
+
+use serde_json::{
+    Value,
+    json,
+};
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::AuthenticationMethod,
+    server::{
+        SelectionPolicy,
+        auth::JsonParameters,
+        tokio::Socks5Listener,
+    },
+};
+use tokio::{
+    net::TcpStream,
+    sync::mpsc::{
+        self,
+        UnboundedReceiver,
+    },
+};
+
+type Parameters = Option<serde_json::Map<String, Value>>;
+
+async fn spawn_proxy() -> IoResult<SocketAddr> {
+    common::serve(listener().await?)
+}
+
+/// Spawns a proxy reporting the parameters each request is made with.
+async fn spawn_recording_proxy() -> IoResult<(SocketAddr, UnboundedReceiver<Parameters>)> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let listener = listener().await?.with_request_filter(move |context, _| {
+        let _ = sender.send(context.parameters.clone());
+        true
+    });
+    Ok((common::serve(listener)?, receiver))
+}
+
+async fn listener() -> IoResult<Socks5Listener> {
+    let listener = Socks5Listener::bind_without_credentials("127.0.0.1:0")
+        .await?
+        .with_authenticator(
+            JsonParameters::new()
+                .with_validator(|parameters| parameters.get("tenant") == Some(&json!("acme"))),
+        )
+        .with_selection_policy(SelectionPolicy::new([
+            AuthenticationMethod::IANA_JSON_PARAMETER_BLOCK,
+        ]));
+    Ok(listener)
+}
+
+async fn connect(
+    proxy: SocketAddr,
+    echo: SocketAddr,
+    tenant: &str,
+) -> Result<TcpStream, ClientError> {
+    let mut parameters = serde_json::Map::new();
+    parameters.insert("tenant".into(), Value::from(tenant));
+    parameters.insert("tags".into(), json!(["batch", "eu"]));
+
+    let stream = TcpStream::connect(proxy).await?;
+    let credentials = sync::Arc::new((Box::default(), Box::default()));
+    Socks5Client::new(stream, credentials)
+        .with_parameters(parameters)
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await
+}
+
+#[tokio::test]
+async fn accepted_parameters() -> Result<(), ClientError> {
+    let proxy = spawn_proxy().await?;
+    let echo = common::spawn_echo().await?;
+
+    let mut stream = connect(proxy, echo, "acme").await?;
+    common::ping(&mut stream).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejected_parameters() -> Result<(), ClientError> {
+    let proxy = spawn_proxy().await?;
+    let echo = common::spawn_echo().await?;
+
+    let result = connect(proxy, echo, "other").await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "parameters should be rejected: {result:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn parameters_are_recorded() -> Result<(), ClientError> {
+    let (proxy, mut parameters) = spawn_recording_proxy().await?;
+    let echo = common::spawn_echo().await?;
+
+    let mut stream = connect(proxy, echo, "acme").await?;
+    common::ping(&mut stream).await?;
+
+    let received = parameters
+        .recv()
+        .await
+        .ok_or_else(|| IoError::from(ErrorKind::UnexpectedEof))?;
+    let expected = json!({ "tenant": "acme", "tags": ["batch", "eu"] });
+    assert_eq!(
+        received.map(Value::Object),
+        Some(expected),
+        "request should see the parameters"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use std::{
+    io::Result as IoResult,
+    net::SocketAddr,
+    sync,
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use serde_json::json;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    jwt::{
+        TokenVerifier,
+        jsonwebtoken::{
+            self,
+            Algorithm,
+            EncodingKey,
+            Header,
+        },
+    },
+    server::{
+        auth::JsonWebToken,
+        tokio::Socks5Listener,
+    },
+};
+use tokio::net::TcpStream;
+
+const SECRET: &[u8] = b"shared secret";
+
+fn verifier() -> IoResult<TokenVerifier> {
+    TokenVerifier::new()
+        .with_hmac_secret(SECRET)
+        .with_ec_public_key(&std::fs::read(common::data("jwt-es256.pub"))?)?
+        .with_ed_public_key(&std::fs::read(common::data("jwt-ed25519.pub"))?)
+        .map(|verifier| verifier.with_audiences([Box::from("proxy")]))
+}
+
+fn unix_time() -> IoResult<u64> {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(std::io::Error::other)?;
+    Ok(elapsed.as_secs())
+}
+
+/// Issues a token for the audience, expiring after the given number of
+/// seconds.
+fn token(algorithm: Algorithm, audience: &str, lifetime: i64) -> IoResult<String> {
+    let encoding_key = match algorithm {
+        | Algorithm::HS256 => EncodingKey::from_secret(SECRET),
+        | Algorithm::ES256 => {
+            EncodingKey::from_ec_pem(&std::fs::read(common::data("jwt-es256.key"))?)
+                .map_err(std::io::Error::other)?
+        },
+        | _ => {
+            EncodingKey::from_ed_pem(&std::fs::read(common::data("jwt-ed25519.key"))?)
+                .map_err(std::io::Error::other)?
+        },
+    };
+    let claims = json!({
+        "sub": "backup-agent",
+        "aud": audience,
+        "exp": unix_time()?.saturating_add_signed(lifetime),
+    });
+    jsonwebtoken::encode(&Header::new(algorithm), &claims, &encoding_key)
+        .map_err(std::io::Error::other)
+}
+
+async fn spawn_proxy() -> IoResult<SocketAddr> {
+    let listener = Socks5Listener::bind_without_credentials("127.0.0.1:0")
+        .await?
+        .with_authenticator(JsonWebToken::new(verifier()?));
+    common::serve(listener)
+}
+
+async fn connect(proxy: SocketAddr, token: String) -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let stream = TcpStream::connect(proxy).await?;
+    let credentials = sync::Arc::new((b"token".as_slice().into(), token.into_bytes().into()));
+    let mut stream = Socks5Client::new(stream, credentials)
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+    Ok(())
+}
+
+#[test]
+fn claims_are_verified() -> IoResult<()> {
+    let verifier = verifier()?;
+
+    for algorithm in [Algorithm::HS256, Algorithm::ES256, Algorithm::EdDSA] {
+        let claims = verifier.verify(token(algorithm, "proxy", 60)?.as_bytes());
+        assert_eq!(
+            claims.and_then(|claims| claims.get("sub").cloned()),
+            Some(json!("backup-agent")),
+            "{algorithm:?} token should be accepted"
+        );
+    }
+
+    assert_eq!(
+        verifier.verify(token(Algorithm::HS256, "proxy", -120)?.as_bytes()),
+        None,
+        "expired token should be rejected"
+    );
+    assert_eq!(
+        verifier.verify(token(Algorithm::ES256, "other", 60)?.as_bytes()),
+        None,
+        "token for another audience should be rejected"
+    );
+    assert_eq!(
+        TokenVerifier::new()
+            .with_hmac_secret(b"another secret")
+            .verify(token(Algorithm::HS256, "proxy", 60)?.as_bytes()),
+        None,
+        "token signed with another key should be rejected"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn token_as_password() -> Result<(), ClientError> {
+    let proxy = spawn_proxy().await?;
+    connect(proxy, token(Algorithm::EdDSA, "proxy", 60)?).await?;
+
+    let result = connect(proxy, token(Algorithm::EdDSA, "proxy", -120)?).await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {result:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::{
+    io::Result as IoResult,
+    net::SocketAddr,
+};
+
+use common::{
+    Credentials,
+    credentials,
+};
+use futures::future::BoxFuture;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::AuthenticationMethod,
+    server::{
+        BoxedStream,
+        ConnectionContext,
+        SelectionPolicy,
+        ServerError,
+        auth::{
+            Authenticator,
+            ChallengeHandshake,
+            Identity,
+            MultiAuthentication,
+            UsernamePassword,
+        },
+    },
+};
+use tokio::net::TcpStream;
+
+/// Rejects every client without a word, as the method has no
+/// sub-negotiation.
+struct Refuse;
+
+impl Authenticator<BoxedStream> for Refuse {
+    fn method(&self) -> AuthenticationMethod {
+        AuthenticationMethod::NO_AUTHENTICATION
+    }
+
+    fn authenticate<'a>(
+        &'a self,
+        _: &'a mut BoxedStream,
+        _: &'a mut ConnectionContext,
+    ) -> BoxFuture<'a, Result<Identity, ServerError>> {
+        Box::pin(async { Err(ServerError::AuthenticationFailed) })
+    }
+}
+
+async fn spawn_proxy() -> IoResult<SocketAddr> {
+    let stages = MultiAuthentication::new()
+        .then(UsernamePassword::new(credentials("user", "secret")))
+        .then(ChallengeHandshake::new(credentials("user", "secret")));
+    serve(stages).await
+}
+
+async fn serve(stages: MultiAuthentication<BoxedStream>) -> IoResult<SocketAddr> {
+    let listener = common::listener()
+        .await?
+        .with_authenticator(stages)
+        .with_selection_policy(SelectionPolicy::new([
+            AuthenticationMethod::IANA_MULTI_AUTHENTICATION_FRAMEWORK,
+        ]));
+    common::serve(listener)
+}
+
+async fn connect(
+    proxy: SocketAddr,
+    echo: SocketAddr,
+    creds: Credentials,
+) -> Result<TcpStream, ClientError> {
+    let stream = TcpStream::connect(proxy).await?;
+    Socks5Client::new(stream, creds)
+        .with_multi_authentication(true)
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await
+}
+
+#[tokio::test]
+async fn all_stages_pass() -> Result<(), ClientError> {
+    let proxy = spawn_proxy().await?;
+    let echo = common::spawn_echo().await?;
+
+    let mut stream = connect(proxy, echo, credentials("user", "secret")).await?;
+    common::ping(&mut stream).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn failed_stage_is_rejected() -> Result<(), ClientError> {
+    let proxy = spawn_proxy().await?;
+    let echo = common::spawn_echo().await?;
+
+    let result = connect(proxy, echo, credentials("user", "wrong")).await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {result:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn silent_stage_failure_is_reported() -> Result<(), ClientError> {
+    let proxy = serve(MultiAuthentication::new().then(Refuse)).await?;
+    let echo = common::spawn_echo().await?;
+
+    let result = connect(proxy, echo, credentials("user", "secret")).await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "failure status should be sent: {result:?}"
+    );
+
+    Ok(())
+}
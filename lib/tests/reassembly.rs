@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use socker::socks5::proto::{
+    Address,
+    messages::UdpHeader,
+    reassembly::{
+        Config,
+        END_OF_SEQUENCE,
+        Reassembler,
+    },
+};
+
+fn header(fragment: u8) -> UdpHeader {
+    UdpHeader {
+        fragment,
+        address: Address::from("example.com"),
+        port: 53,
+    }
+}
+
+#[test]
+fn unfragmented_datagram_is_passed_through() {
+    let mut reassembler = Reassembler::new(Config::default());
+
+    let datagram = reassembler.push(&header(0), b"whole");
+    assert_eq!(
+        datagram.map(|datagram| datagram.data),
+        Some(b"whole".as_slice().into()),
+        "datagram should be passed through"
+    );
+    assert_eq!(reassembler.deadline(), None, "nothing should be queued");
+}
+
+#[test]
+fn ordered_fragments_are_reassembled() {
+    let mut reassembler = Reassembler::new(Config::default());
+
+    assert!(reassembler.push(&header(1), b"one ").is_none());
+    assert!(reassembler.deadline().is_some(), "queue should be timed");
+    assert!(reassembler.push(&header(2), b"two ").is_none());
+    let datagram = reassembler.push(&header(3 | END_OF_SEQUENCE), b"three");
+
+    let datagram = datagram.map(|datagram| (datagram.header, datagram.data));
+    assert!(
+        matches!(
+            &datagram,
+            Some((header, data))
+                if header.fragment == 0
+                    && header.address == Address::from("example.com")
+                    && header.port == 53
+                    && **data == *b"one two three"
+        ),
+        "datagram should be reassembled: {datagram:?}"
+    );
+    assert_eq!(reassembler.deadline(), None, "queue should be released");
+}
+
+#[test]
+fn lost_fragment_drops_the_datagram() {
+    let mut reassembler = Reassembler::new(Config::default());
+
+    assert!(reassembler.push(&header(1), b"one ").is_none());
+    assert!(
+        reassembler
+            .push(&header(3 | END_OF_SEQUENCE), b"three")
+            .is_none(),
+        "datagram missing a fragment should be dropped"
+    );
+    assert_eq!(reassembler.deadline(), None, "queue should be released");
+}
+
+#[test]
+fn repeated_position_restarts_the_queue() {
+    let mut reassembler = Reassembler::new(Config::default());
+
+    assert!(reassembler.push(&header(1), b"one ").is_none());
+    assert!(reassembler.push(&header(2), b"two ").is_none());
+    assert!(reassembler.push(&header(1), b"uno ").is_none());
+    let datagram = reassembler.push(&header(2 | END_OF_SEQUENCE), b"dos");
+    assert_eq!(
+        datagram.map(|datagram| datagram.data),
+        Some(b"uno dos".as_slice().into()),
+        "fragments before the restart should be abandoned"
+    );
+}
+
+#[test]
+fn lower_position_restarts_the_queue() {
+    let mut reassembler = Reassembler::new(Config::default());
+
+    assert!(reassembler.push(&header(1), b"one ").is_none());
+    assert!(reassembler.push(&header(2), b"two ").is_none());
+    assert!(reassembler.push(&header(3), b"three ").is_none());
+    assert!(
+        reassembler.push(&header(2), b"two ").is_none(),
+        "lower position should restart the queue"
+    );
+    assert!(
+        reassembler
+            .push(&header(3 | END_OF_SEQUENCE), b"three")
+            .is_none(),
+        "restarted queue should miss its first fragment"
+    );
+
+    assert!(reassembler.push(&header(1), b"one ").is_none());
+    let datagram = reassembler.push(&header(2 | END_OF_SEQUENCE), b"two");
+    assert_eq!(
+        datagram.map(|datagram| datagram.data),
+        Some(b"one two".as_slice().into()),
+        "new sequence should be reassembled"
+    );
+}
+
+#[test]
+fn other_destination_restarts_the_queue() {
+    let mut reassembler = Reassembler::new(Config::default());
+    let other = |fragment| {
+        UdpHeader {
+            port: 5353,
+            ..header(fragment)
+        }
+    };
+
+    assert!(reassembler.push(&header(1), b"one ").is_none());
+    assert!(reassembler.push(&other(1), b"uno ").is_none());
+    let datagram = reassembler.push(&other(2 | END_OF_SEQUENCE), b"dos");
+    assert!(
+        matches!(
+            &datagram,
+            Some(datagram) if datagram.header.port == 5353 && *datagram.data == *b"uno dos"
+        ),
+        "fragments to the other destination should be reassembled: {datagram:?}"
+    );
+}
+
+#[test]
+fn lone_end_of_sequence_is_dropped() {
+    let mut reassembler = Reassembler::new(Config::default());
+
+    assert!(
+        reassembler
+            .push(&header(END_OF_SEQUENCE), b"lone")
+            .is_none()
+    );
+    assert_eq!(reassembler.deadline(), None, "nothing should be queued");
+}
+
+#[test]
+fn expired_queue_is_dropped() {
+    let mut reassembler = Reassembler::new(Config {
+        timeout: Duration::from_millis(10),
+        ..Config::default()
+    });
+
+    assert!(reassembler.push(&header(1), b"one ").is_none());
+    std::thread::sleep(Duration::from_millis(20));
+    reassembler.expire();
+    assert_eq!(reassembler.deadline(), None, "queue should be dropped");
+
+    assert!(reassembler.push(&header(1), b"one ").is_none());
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(
+        reassembler
+            .push(&header(2 | END_OF_SEQUENCE), b"two")
+            .is_none(),
+        "late fragment should not complete the datagram"
+    );
+}
+
+#[test]
+fn oversized_queue_is_dropped() {
+    let mut reassembler = Reassembler::new(Config {
+        max_queue_size: 8,
+        ..Config::default()
+    });
+
+    assert!(reassembler.push(&header(1), b"12345").is_none());
+    assert!(
+        reassembler
+            .push(&header(2 | END_OF_SEQUENCE), b"6789")
+            .is_none()
+    );
+    assert_eq!(reassembler.deadline(), None, "queue should be dropped");
+
+    assert!(reassembler.push(&header(1), b"1234").is_none());
+    let datagram = reassembler.push(&header(2 | END_OF_SEQUENCE), b"5678");
+    assert_eq!(
+        datagram.map(|datagram| datagram.data),
+        Some(b"12345678".as_slice().into()),
+        "a queue within the limit should be reassembled"
+    );
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::net::{
+    IpAddr,
+    Ipv4Addr,
+};
+
+use common::credentials;
+use socker::socks5::{
+    client::{
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::{
+        Address,
+        Reply,
+    },
+};
+use tokio::{
+    io::{
+        AsyncReadExt,
+        AsyncWriteExt,
+    },
+    net::TcpStream,
+};
+
+#[tokio::test]
+async fn domain_is_resolved() -> Result<(), ClientError> {
+    let proxy = common::serve(common::listener().await?)?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let address = Socks5Client::new(stream, credentials("user", "secret"))
+        .resolve("localhost")
+        .await?;
+    assert!(
+        match address {
+            | Address::Ipv4(addr) => addr.is_loopback(),
+            | Address::Ipv6(addr) => addr.is_loopback(),
+            | Address::Domain(_) => false,
+        },
+        "localhost should resolve to a loopback address: {address:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reverse_lookup_is_not_supported() -> Result<(), ClientError> {
+    let proxy = common::serve(common::listener().await?)?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let result = Socks5Client::new(stream, credentials("user", "secret"))
+        .resolve_ptr(IpAddr::V4(Ipv4Addr::LOCALHOST))
+        .await;
+    assert!(
+        matches!(
+            result,
+            Err(ClientError::RequestFailed(Reply::COMMAND_NOT_SUPPORTED))
+        ),
+        "the system resolver has no reverse lookups: {result:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reverse_lookup_reply_is_a_domain() -> Result<(), ClientError> {
+    let (client, mut proxy) = tokio::io::duplex(64);
+    let proxy = tokio::spawn(async move {
+        let mut greeting = [0; 4];
+        proxy.read_exact(&mut greeting).await?;
+        proxy.write_all(&[0x05, 0x00]).await?;
+
+        let mut request = [0; 10];
+        proxy.read_exact(&mut request).await?;
+        assert_eq!(
+            request,
+            [0x05, 0xF1, 0x00, 0x01, 192, 0, 2, 1, 0x00, 0x00],
+            "client should send a RESOLVE_PTR request"
+        );
+        proxy
+            .write_all(&[
+                0x05, 0x00, 0x00, 0x03, 4, b'h', b'o', b's', b't', 0x00, 0x00,
+            ])
+            .await
+    });
+
+    let address = Socks5Client::from_stream(client, credentials("", ""))
+        .resolve_ptr(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)))
+        .await?;
+    assert_eq!(address, Address::from("host"));
+
+    proxy.await.map_err(std::io::Error::from)??;
+    Ok(())
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::credentials;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::AuthenticationMethod,
+    server::{
+        SelectionPolicy,
+        auth::NoAuthentication,
+    },
+};
+use tokio::net::TcpStream;
+
+#[tokio::test]
+async fn unacceptable_methods_are_refused() -> Result<(), ClientError> {
+    let proxy = common::serve(common::listener().await?)?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let mut client = Socks5Client::new(stream, credentials("user", "secret"));
+    let choice = client
+        .perform_handshake(
+            [
+                AuthenticationMethod::NO_AUTHENTICATION,
+                AuthenticationMethod::GSSAPI,
+            ]
+            .into(),
+        )
+        .await?;
+    assert_eq!(
+        choice,
+        AuthenticationMethod::NO_ACCEPTABLE_METHODS,
+        "server should refuse the offered methods"
+    );
+
+    let mut buffer = [0; 1];
+    let read = futures::AsyncReadExt::read(client.stream(), &mut buffer).await?;
+    assert_eq!(read, 0, "server should close the connection");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn preference_order_is_followed() -> Result<(), ClientError> {
+    let listener = common::listener()
+        .await?
+        .with_authenticator(NoAuthentication)
+        .with_selection_policy(SelectionPolicy::new([
+            AuthenticationMethod::USERNAME_PASSWORD,
+            AuthenticationMethod::NO_AUTHENTICATION,
+        ]));
+    let proxy = common::serve(listener)?;
+
+    let offered = [
+        AuthenticationMethod::NO_AUTHENTICATION,
+        AuthenticationMethod::USERNAME_PASSWORD,
+    ];
+    let stream = TcpStream::connect(proxy).await?;
+    let choice = Socks5Client::new(stream, credentials("user", "secret"))
+        .perform_handshake(offered.into())
+        .await?;
+    assert_eq!(choice, AuthenticationMethod::USERNAME_PASSWORD);
+
+    let stream = TcpStream::connect(proxy).await?;
+    let choice = Socks5Client::new(stream, credentials("user", "secret"))
+        .perform_handshake([AuthenticationMethod::NO_AUTHENTICATION].into())
+        .await?;
+    assert_eq!(choice, AuthenticationMethod::NO_AUTHENTICATION);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn required_authentication_excludes_no_authentication() -> Result<(), ClientError> {
+    let listener = common::listener()
+        .await?
+        .with_authenticator(NoAuthentication)
+        .with_selection_policy(
+            SelectionPolicy::new([AuthenticationMethod::NO_AUTHENTICATION])
+                .require_authentication(true),
+        );
+    let proxy = common::serve(listener)?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let choice = Socks5Client::new(stream, credentials("user", "secret"))
+        .perform_handshake([AuthenticationMethod::NO_AUTHENTICATION].into())
+        .await?;
+    assert_eq!(choice, AuthenticationMethod::NO_ACCEPTABLE_METHODS);
+
+    Ok(())
+}
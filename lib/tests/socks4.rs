@@ -0,0 +1,121 @@
+mod common;
+
+use std::net::{
+    Ipv6Addr,
+    SocketAddr,
+};
+
+use socker::{
+    socks4::{
+        client::{
+            Client,
+            ClientError,
+            tokio::Socks4Client,
+        },
+        proto::Reply,
+    },
+    socks5::{
+        proto::CommandType,
+        server::IpNetwork,
+    },
+};
+use tokio::net::TcpStream;
+
+async fn connect(proxy: SocketAddr) -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let stream = TcpStream::connect(proxy).await?;
+    let mut stream = Socks4Client::new(stream, b"user".as_slice().into())
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+    Ok(())
+}
+
+async fn spawn_trusted_proxy() -> Result<SocketAddr, ClientError> {
+    let Some(network) = IpNetwork::parse("127.0.0.0/8") else {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput).into());
+    };
+    let listener = common::listener()
+        .await?
+        .with_socks4(true)
+        .with_trusted_network(network);
+    Ok(common::serve(listener)?)
+}
+
+#[tokio::test]
+async fn unauthenticated_client_is_refused() -> Result<(), ClientError> {
+    let listener = common::listener().await?.with_socks4(true);
+    let proxy = common::serve(listener)?;
+
+    let result = connect(proxy).await;
+    assert!(
+        matches!(result, Err(ClientError::RequestFailed(Reply::REJECTED))),
+        "request should be rejected: {result:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn trusted_client_is_served() -> Result<(), ClientError> {
+    let proxy = spawn_trusted_proxy().await?;
+    connect(proxy).await
+}
+
+#[tokio::test]
+async fn request_filter_applies() -> Result<(), ClientError> {
+    let Some(network) = IpNetwork::parse("127.0.0.0/8") else {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput).into());
+    };
+    let blocked = common::spawn_echo().await?;
+    let listener = common::listener()
+        .await?
+        .with_socks4(true)
+        .with_trusted_network(network)
+        .with_request_filter(move |_, request| {
+            request.command == CommandType::CONNECT && request.port != blocked.port()
+        });
+    let proxy = common::serve(listener)?;
+    connect(proxy).await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let result = Socks4Client::new(stream, Box::default())
+        .connect_to_target(blocked.ip().into(), blocked.port())
+        .await;
+    assert!(
+        matches!(result, Err(ClientError::RequestFailed(Reply::REJECTED))),
+        "filtered request should be rejected: {result:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn domain_is_resolved_by_the_proxy() -> Result<(), ClientError> {
+    let proxy = spawn_trusted_proxy().await?;
+    let echo = common::spawn_echo().await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let mut stream = Socks4Client::new(stream, Box::default())
+        .connect_to_target("localhost".into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ipv6_target_is_not_supported() -> Result<(), ClientError> {
+    let proxy = spawn_trusted_proxy().await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let result = Socks4Client::new(stream, Box::default())
+        .connect_to_target(Ipv6Addr::LOCALHOST.into(), 80)
+        .await;
+    assert!(
+        matches!(result, Err(ClientError::AddressNotSupported(_))),
+        "IPv6 should be refused by the client: {result:?}"
+    );
+
+    Ok(())
+}
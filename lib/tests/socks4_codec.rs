@@ -0,0 +1,158 @@
+use std::net::Ipv4Addr;
+
+use futures::{
+    executor::block_on,
+    io::Cursor,
+};
+use socker::{
+    codec::{
+        Decoder,
+        Encoder,
+    },
+    socks4::proto::{
+        CommandType,
+        ConversionError,
+        Reply,
+        messages::{
+            Request,
+            Response,
+        },
+    },
+};
+
+fn encode(request: &Request) -> Result<Vec<u8>, ConversionError> {
+    let mut writer = Cursor::new(Vec::new());
+    block_on(request.write_to(&mut writer))?;
+    Ok(writer.into_inner())
+}
+
+#[test]
+fn ipv4_request_round_trips() -> Result<(), ConversionError> {
+    let request = Request {
+        command: CommandType::CONNECT,
+        address: Ipv4Addr::new(192, 0, 2, 1).into(),
+        port:    80,
+        user_id: b"alice".as_slice().into(),
+    };
+
+    let bytes = encode(&request)?;
+    assert_eq!(bytes, b"\x04\x01\x00\x50\xc0\x00\x02\x01alice\x00");
+
+    let decoded = block_on(Request::read_from(&mut Cursor::new(bytes)))?;
+    assert_eq!(decoded.command, CommandType::CONNECT);
+    assert_eq!(decoded.address, request.address);
+    assert_eq!(decoded.port, 80);
+    assert_eq!(decoded.user_id, request.user_id);
+
+    Ok(())
+}
+
+#[test]
+fn domain_request_round_trips() -> Result<(), ConversionError> {
+    let request = Request {
+        command: CommandType::BIND,
+        address: "example.com".into(),
+        port:    443,
+        user_id: Box::default(),
+    };
+
+    let bytes = encode(&request)?;
+    assert_eq!(
+        bytes,
+        b"\x04\x02\x01\xbb\x00\x00\x00\x01\x00example.com\x00"
+    );
+
+    let decoded = block_on(Request::read_from(&mut Cursor::new(bytes)))?;
+    assert_eq!(decoded.command, CommandType::BIND);
+    assert_eq!(decoded.address, request.address);
+    assert_eq!(decoded.port, 443);
+    assert!(decoded.user_id.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn domain_marker_address_is_rejected() {
+    for address in [Ipv4Addr::new(0, 0, 0, 1), Ipv4Addr::new(0, 0, 0, 255)] {
+        let request = Request {
+            command: CommandType::CONNECT,
+            address: address.into(),
+            port:    80,
+            user_id: Box::default(),
+        };
+        let result = encode(&request);
+        assert!(
+            matches!(result, Err(ConversionError::MalformedMessage)),
+            "{address} should not be encoded: {result:?}"
+        );
+    }
+
+    let request = Request {
+        command: CommandType::CONNECT,
+        address: Ipv4Addr::UNSPECIFIED.into(),
+        port:    80,
+        user_id: Box::default(),
+    };
+    assert!(encode(&request).is_ok(), "0.0.0.0 is not a marker");
+}
+
+#[test]
+fn invalid_fields_are_rejected() {
+    let requests = [
+        Request {
+            command: CommandType::CONNECT,
+            address: Ipv4Addr::LOCALHOST.into(),
+            port:    80,
+            user_id: b"a\x00b".as_slice().into(),
+        },
+        Request {
+            command: CommandType::CONNECT,
+            address: "x".repeat(256).as_str().into(),
+            port:    80,
+            user_id: Box::default(),
+        },
+    ];
+    for request in requests {
+        let mut writer = Cursor::new(Vec::new());
+        let result = block_on(request.write_to(&mut writer));
+        assert!(
+            matches!(result, Err(ConversionError::MalformedMessage)),
+            "request should not be encoded: {result:?}"
+        );
+        assert!(writer.get_ref().is_empty(), "nothing should be written");
+    }
+}
+
+#[test]
+fn overlong_field_is_not_decoded() {
+    let mut bytes = b"\x04\x01\x00\x50\x7f\x00\x00\x01".to_vec();
+    bytes.extend_from_slice(&[b'x'; 256]);
+    bytes.push(0x00);
+
+    let result = block_on(Request::read_from(&mut Cursor::new(bytes)));
+    assert!(
+        matches!(result, Err(ConversionError::MalformedMessage)),
+        "request should not be decoded: {result:?}"
+    );
+}
+
+#[test]
+fn response_round_trips() -> Result<(), ConversionError> {
+    let response = Response {
+        reply:   Reply::GRANTED,
+        address: Ipv4Addr::new(198, 51, 100, 7),
+        port:    1080,
+    };
+
+    let mut writer = Cursor::new(Vec::new());
+    block_on(response.write_to(&mut writer))?;
+    let bytes = writer.into_inner();
+    assert_eq!(bytes, b"\x00\x5a\x04\x38\xc6\x33\x64\x07");
+
+    let decoded = block_on(Response::read_from(&mut Cursor::new(bytes)))?;
+    assert!(decoded.reply.is_success());
+    assert_eq!(decoded.address, response.address);
+    assert_eq!(decoded.port, 1080);
+
+    Ok(())
+}
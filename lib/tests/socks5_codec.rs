@@ -0,0 +1,133 @@
+use std::net::{
+    Ipv4Addr,
+    Ipv6Addr,
+};
+
+use futures::{
+    executor::block_on,
+    io::Cursor,
+};
+use socker::{
+    codec::{
+        Decoder,
+        Encoder,
+    },
+    socks5::proto::{
+        Address,
+        ConversionError,
+        messages::{
+            UdpDatagram,
+            UdpHeader,
+        },
+    },
+};
+
+fn addresses() -> [Address; 4] {
+    [
+        Ipv4Addr::new(192, 0, 2, 1).into(),
+        Ipv6Addr::LOCALHOST.into(),
+        "example.com".into(),
+        "x".repeat(255).into(),
+    ]
+}
+
+#[test]
+fn addresses_round_trip() -> Result<(), ConversionError> {
+    for address in addresses() {
+        let mut buffer = Vec::new();
+        address.encode_into(&mut buffer)?;
+        buffer.push(0xAA);
+        let (parsed, rest) = Address::parse(&buffer)?;
+        assert_eq!(parsed, address);
+        assert_eq!(rest, [0xAA], "only the address should be consumed");
+
+        let mut writer = Cursor::new(Vec::new());
+        block_on(address.write_to(&mut writer))?;
+        assert_eq!(
+            Some(writer.get_ref().as_slice()),
+            buffer.get(.. buffer.len().saturating_sub(1)),
+            "both encoders should agree"
+        );
+        let read = block_on(Address::read_from(&mut Cursor::new(writer.into_inner())))?;
+        assert_eq!(read, address);
+    }
+    Ok(())
+}
+
+#[test]
+fn long_domain_is_rejected() {
+    let address = Address::from("x".repeat(256));
+
+    let mut buffer = Vec::new();
+    let result = address.encode_into(&mut buffer);
+    assert!(
+        matches!(result, Err(ConversionError::MalformedMessage)),
+        "encoding should fail: {result:?}"
+    );
+    assert!(buffer.is_empty(), "nothing should be written");
+
+    let result = block_on(address.write_to(&mut Cursor::new(Vec::new())));
+    assert!(
+        matches!(result, Err(ConversionError::MalformedMessage)),
+        "encoding should fail: {result:?}"
+    );
+
+    let header = UdpHeader {
+        fragment: 0,
+        address,
+        port: 53,
+    };
+    let mut buffer = b"prefix".to_vec();
+    let result = header.encode_into(&mut buffer);
+    assert!(
+        matches!(result, Err(ConversionError::MalformedMessage)),
+        "encoding should fail: {result:?}"
+    );
+    assert_eq!(buffer, b"prefix", "no partial header should be written");
+}
+
+#[test]
+fn datagrams_round_trip() -> Result<(), ConversionError> {
+    for address in addresses() {
+        let datagram = UdpDatagram {
+            header: UdpHeader {
+                fragment: 0x81,
+                address:  address.clone(),
+                port:     5353,
+            },
+            data:   b"payload".as_slice().into(),
+        };
+        let bytes = datagram.to_bytes()?;
+
+        let parsed = UdpDatagram::parse(&bytes)?;
+        assert_eq!(parsed.header.fragment, 0x81);
+        assert_eq!(parsed.header.address, address);
+        assert_eq!(parsed.header.port, 5353);
+        assert_eq!(&*parsed.data, b"payload");
+
+        let read = block_on(UdpDatagram::read_from(&mut Cursor::new(bytes)))?;
+        assert_eq!(read.header.address, address);
+        assert_eq!(&*read.data, b"payload");
+    }
+    Ok(())
+}
+
+#[test]
+fn truncated_header_is_malformed() -> Result<(), ConversionError> {
+    let header = UdpHeader {
+        fragment: 0,
+        address:  "example.com".into(),
+        port:     53,
+    };
+    let mut buffer = Vec::new();
+    header.encode_into(&mut buffer)?;
+
+    for length in 0 .. buffer.len() {
+        let result = UdpHeader::parse(buffer.get(.. length).unwrap_or_default());
+        assert!(
+            matches!(result, Err(ConversionError::MalformedMessage)),
+            "{length} bytes should not parse: {result:?}"
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use std::{
+    io::Result as IoResult,
+    net::SocketAddr,
+    sync,
+    time::Duration,
+};
+
+use common::Credentials;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::AuthenticationMethod,
+    server::{
+        auth::{
+            Identity,
+            TlsUpgrade,
+        },
+        credentials::MemoryStore,
+    },
+    tls::{
+        self,
+        Acceptor,
+        Connector,
+        rustls::pki_types::ServerName,
+    },
+};
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncReadExt as _,
+        AsyncWrite,
+        AsyncWriteExt as _,
+    },
+    net::TcpStream,
+};
+
+fn credentials() -> Credentials {
+    common::credentials("user", "secret")
+}
+
+fn acceptor() -> IoResult<Acceptor> {
+    tls::acceptor(common::data("server.pem"), common::data("server.key"))
+}
+
+fn connector() -> IoResult<Connector> {
+    tls::connector(common::data("ca.pem"))
+}
+
+fn server_name() -> ServerName<'static> {
+    ServerName::from(std::net::Ipv4Addr::LOCALHOST)
+}
+
+#[tokio::test]
+async fn tls_listener() -> Result<(), ClientError> {
+    let listener = common::listener().await?.with_tls(acceptor()?);
+    let proxy = common::serve(listener)?;
+    let echo = common::spawn_echo().await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let mut stream = Socks5Client::connect_tls(stream, &connector()?, server_name(), credentials())
+        .await?
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+
+    Ok(())
+}
+
+async fn spawn_upgrade_proxy(timeout: Duration) -> IoResult<SocketAddr> {
+    let store: MemoryStore = [("user", "secret")].into_iter().collect();
+    let upgrade = TlsUpgrade::new(acceptor()?, store).with_timeout(timeout);
+    let listener = common::listener().await?.with_authenticator(upgrade);
+    common::serve(listener)
+}
+
+#[tokio::test]
+async fn tls_upgrade() -> Result<(), ClientError> {
+    let proxy = spawn_upgrade_proxy(Duration::from_secs(10)).await?;
+    let echo = common::spawn_echo().await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let mut stream = Socks5Client::new(stream, credentials())
+        .connect_with_tls_upgrade(&connector()?, server_name(), echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn wrong_credentials_are_rejected_after_the_upgrade() -> Result<(), ClientError> {
+    let proxy = spawn_upgrade_proxy(Duration::from_secs(10)).await?;
+    let echo = common::spawn_echo().await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let result = Socks5Client::new(stream, common::credentials("user", "wrong"))
+        .connect_with_tls_upgrade(&connector()?, server_name(), echo.ip().into(), echo.port())
+        .await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {result:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stalled_upgrade_is_dropped() -> Result<(), ClientError> {
+    let proxy = spawn_upgrade_proxy(Duration::from_millis(200)).await?;
+
+    // The client picks the method, but never sends its hello.
+    let mut stream = TcpStream::connect(proxy).await?;
+    stream.write_all(&[5, 1, 0x06]).await?;
+    let mut choice = [0; 2];
+    stream.read_exact(&mut choice).await?;
+    assert_eq!(choice, [5, 0x06], "server should choose the TLS method");
+
+    let mut buffer = [0; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buffer)).await;
+    assert!(
+        matches!(read, Ok(Ok(0))),
+        "server should close the connection: {read:?}"
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stalled_handshake_is_dropped() -> Result<(), ClientError> {
+    let listener = common::listener()
+        .await?
+        .with_tls(acceptor()?)
+        .with_tls_timeout(Duration::from_millis(200));
+    let proxy = common::serve(listener)?;
+
+    // The client never sends its hello.
+    let mut stream = TcpStream::connect(proxy).await?;
+    let mut buffer = [0; 1];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buffer)).await;
+    assert!(
+        matches!(read, Ok(Ok(0))),
+        "server should close the connection: {read:?}"
+    );
+
+    Ok(())
+}
+
+async fn spawn_mtls_proxy(accepted: &'static str) -> IoResult<SocketAddr> {
+    let acceptor = tls::acceptor_with_client_auth(
+        common::data("server.pem"),
+        common::data("server.key"),
+        common::data("ca.pem"),
+    )?;
+    let listener = common::listener()
+        .await?
+        .with_tls(acceptor)
+        .with_client_certificates(move |certificate| {
+            (certificate.name() == Some(accepted)).then(|| Identity::Certificate(accepted.into()))
+        });
+    let proxy = common::serve(listener)?;
+    Ok(proxy)
+}
+
+async fn connect_mtls(
+    proxy: SocketAddr,
+    echo: SocketAddr,
+) -> Result<impl AsyncRead + AsyncWrite + Unpin, ClientError> {
+    let connector = tls::connector_with_client_auth(
+        common::data("ca.pem"),
+        common::data("client.pem"),
+        common::data("client.key"),
+    )?;
+    let stream = TcpStream::connect(proxy).await?;
+    // The certificate replaces the password.
+    let credentials = sync::Arc::new((Box::default(), Box::default()));
+    Socks5Client::connect_tls(stream, &connector, server_name(), credentials)
+        .await?
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await
+}
+
+#[tokio::test]
+async fn client_certificate_is_accepted() -> Result<(), ClientError> {
+    let proxy = spawn_mtls_proxy("backup.example.com").await?;
+    let echo = common::spawn_echo().await?;
+
+    let mut stream = connect_mtls(proxy, echo).await?;
+    common::ping(&mut stream).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unmapped_client_certificate_is_rejected() -> Result<(), ClientError> {
+    let proxy = spawn_mtls_proxy("other.example.com").await?;
+    let echo = common::spawn_echo().await?;
+
+    let result = connect_mtls(proxy, echo).await;
+    assert!(result.is_err(), "connection should be rejected");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn missing_client_certificate_is_rejected() -> Result<(), ClientError> {
+    let proxy = spawn_mtls_proxy("backup.example.com").await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let result = Socks5Client::connect_tls(stream, &connector()?, server_name(), credentials())
+        .await?
+        .perform_handshake([AuthenticationMethod::USERNAME_PASSWORD].into())
+        .await;
+    assert!(
+        result.is_err(),
+        "handshake should fail without a certificate"
+    );
+
+    Ok(())
+}
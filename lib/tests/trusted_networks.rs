@@ -0,0 +1,107 @@
+mod common;
+
+use std::{
+    io::{
+        Error as IoError,
+        ErrorKind,
+        Result as IoResult,
+    },
+    net::{
+        AddrParseError,
+        IpAddr,
+        SocketAddr,
+    },
+};
+
+use common::{
+    Credentials,
+    credentials,
+};
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    server::IpNetwork,
+};
+use tokio::net::TcpStream;
+
+fn network(network: &str) -> IoResult<IpNetwork> {
+    IpNetwork::parse(network).ok_or_else(|| IoError::from(ErrorKind::InvalidInput))
+}
+
+fn addr(addr: &str) -> Result<IpAddr, AddrParseError> {
+    addr.parse()
+}
+
+async fn spawn_proxy(trusted: &str) -> IoResult<SocketAddr> {
+    let network = network(trusted)?;
+    let listener = common::listener().await?.with_trusted_network(network);
+    common::serve(listener)
+}
+
+async fn connect(proxy: SocketAddr, creds: Credentials) -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let stream = TcpStream::connect(proxy).await?;
+    let mut stream = Socks5Client::new(stream, creds)
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+    Ok(())
+}
+
+#[test]
+fn networks_are_matched() -> Result<(), Box<dyn std::error::Error>> {
+    let ipv4 = network("10.1.0.0/16")?;
+    assert!(ipv4.contains(addr("10.1.2.3")?), "address should match");
+    assert!(
+        ipv4.contains(addr("::ffff:10.1.2.3")?),
+        "mapped address should match"
+    );
+    assert!(
+        !ipv4.contains(addr("10.2.0.1")?),
+        "address should not match"
+    );
+    assert!(
+        !ipv4.contains(addr("::1")?),
+        "IPv6 address should not match"
+    );
+
+    let ipv6 = network("fd00::/8")?;
+    assert!(ipv6.contains(addr("fd12::1")?), "address should match");
+    assert!(!ipv6.contains(addr("fe80::1")?), "address should not match");
+
+    let everything = network("0.0.0.0/0")?;
+    assert!(
+        everything.contains(addr("192.0.2.1")?),
+        "address should match"
+    );
+
+    assert_eq!(IpNetwork::parse("10.0.0.0/33"), None, "prefix is too long");
+    assert_eq!(
+        IpNetwork::parse("10.0.0.0/x"),
+        None,
+        "prefix is not a number"
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn trusted_client_skips_authentication() -> Result<(), ClientError> {
+    let proxy = spawn_proxy("127.0.0.0/8").await?;
+    connect(proxy, credentials("user", "wrong")).await
+}
+
+#[tokio::test]
+async fn other_clients_must_authenticate() -> Result<(), ClientError> {
+    let proxy = spawn_proxy("10.0.0.0/8").await?;
+
+    let result = connect(proxy, credentials("user", "wrong")).await;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {result:?}"
+    );
+
+    connect(proxy, credentials("user", "secret")).await
+}
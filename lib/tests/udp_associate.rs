@@ -0,0 +1,219 @@
+mod common;
+
+use std::{
+    io::Result as IoResult,
+    net::{
+        Ipv4Addr,
+        SocketAddr,
+    },
+    time::Duration,
+};
+
+use common::credentials;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::{
+            Socks5Client,
+            Socks5UdpSocket,
+        },
+        username_password_auth_impl,
+    },
+    proto::{
+        Address,
+        AuthenticationMethod,
+        messages::UdpHeader,
+        reassembly::END_OF_SEQUENCE,
+    },
+};
+use tokio::{
+    io::{
+        AsyncReadExt,
+        AsyncWriteExt,
+    },
+    net::{
+        TcpListener,
+        TcpStream,
+        UdpSocket,
+    },
+};
+
+/// Spawns a server echoing the datagrams it receives.
+async fn spawn_udp_echo() -> IoResult<SocketAddr> {
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    let address = socket.local_addr()?;
+    tokio::spawn(async move {
+        let mut buffer = [0; 64];
+        while let Ok((length, source)) = socket.recv_from(&mut buffer).await {
+            let _ = socket
+                .send_to(buffer.get(.. length).unwrap_or_default(), source)
+                .await;
+        }
+    });
+    Ok(address)
+}
+
+#[tokio::test]
+async fn datagrams_are_relayed() -> Result<(), ClientError> {
+    let proxy = common::serve(common::listener().await?)?;
+    let echo = spawn_udp_echo().await?;
+
+    let stream = TcpStream::connect(proxy).await?;
+    let association = Socks5Client::new(stream, credentials("user", "secret"))
+        .associate_udp(Ipv4Addr::UNSPECIFIED.into(), 0)
+        .await?;
+    let mut socket = Socks5UdpSocket::new(association).await?;
+    socket
+        .send_to(b"ping", echo.ip().into(), echo.port())
+        .await?;
+
+    let mut buffer = [0; 16];
+    let (length, address, port) = socket.recv_from(&mut buffer).await?;
+    assert_eq!(
+        buffer.get(.. length),
+        Some(b"ping".as_slice()),
+        "datagram should be relayed"
+    );
+    assert_eq!(
+        (address, port),
+        (echo.ip().into(), echo.port()),
+        "origin should be reported"
+    );
+
+    Ok(())
+}
+
+/// Sets up an association announcing the given source, and returns the
+/// client to keep it alive alongside with a socket connected to the relay.
+async fn associate(
+    proxy: SocketAddr,
+    source: Address,
+) -> Result<(Socks5Client, UdpSocket), ClientError> {
+    let stream = TcpStream::connect(proxy).await?;
+    let mut client = Socks5Client::new(stream, credentials("user", "secret"));
+    client
+        .perform_handshake([AuthenticationMethod::USERNAME_PASSWORD].into())
+        .await?;
+    username_password_auth_impl(
+        &mut client,
+        b"user".as_slice().into(),
+        b"secret".as_slice().into(),
+    )
+    .await?;
+
+    let (relay_addr, relay_port) = client.send_udp_associate_request(source, 0).await?;
+    assert_eq!(
+        relay_addr,
+        Address::from(proxy.ip()),
+        "relay should be on the proxy's address"
+    );
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await?;
+    socket.connect((proxy.ip(), relay_port)).await?;
+    Ok((client, socket))
+}
+
+async fn send(
+    socket: &UdpSocket,
+    fragment: u8,
+    target: SocketAddr,
+    payload: &[u8],
+) -> Result<(), ClientError> {
+    let header = UdpHeader {
+        fragment,
+        address: target.ip().into(),
+        port: target.port(),
+    };
+    let mut packet = Vec::new();
+    header.encode_into(&mut packet)?;
+    packet.extend_from_slice(payload);
+    socket.send(&packet).await?;
+    Ok(())
+}
+
+async fn receive(socket: &UdpSocket) -> Result<(UdpHeader, Vec<u8>), ClientError> {
+    let mut buffer = [0; 64];
+    let length = tokio::time::timeout(Duration::from_secs(5), socket.recv(&mut buffer))
+        .await
+        .map_err(std::io::Error::from)??;
+    let (header, payload) = UdpHeader::parse(buffer.get(.. length).unwrap_or_default())?;
+    Ok((header, payload.to_vec()))
+}
+
+#[tokio::test]
+async fn client_is_the_control_connection_peer() -> Result<(), ClientError> {
+    let proxy = common::serve(common::listener().await?)?;
+    let echo = spawn_udp_echo().await?;
+
+    // The request names another host, which must not take over the
+    // association.
+    let (_client, socket) = associate(proxy, Ipv4Addr::new(192, 0, 2, 1).into()).await?;
+    send(&socket, 0, echo, b"ping").await?;
+
+    let (header, payload) = receive(&socket).await?;
+    assert_eq!(payload, b"ping", "datagram should be relayed");
+    assert_eq!(header.port, echo.port(), "origin should be reported");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fragments_are_reassembled() -> Result<(), ClientError> {
+    let proxy = common::serve(common::listener().await?)?;
+    let echo = spawn_udp_echo().await?;
+
+    let (_client, socket) = associate(proxy, Ipv4Addr::UNSPECIFIED.into()).await?;
+    send(&socket, 1, echo, b"pi").await?;
+    send(&socket, 2 | END_OF_SEQUENCE, echo, b"ng").await?;
+
+    let (header, payload) = receive(&socket).await?;
+    assert_eq!(payload, b"ping", "datagram should be reassembled");
+    assert_eq!(header.fragment, 0, "reply should not be fragmented");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn closed_association_ends_the_socket() -> Result<(), ClientError> {
+    let relay = UdpSocket::bind("127.0.0.1:0").await?;
+    let relay_port = relay.local_addr()?.port().to_be_bytes();
+
+    // A proxy that closes the connection right after the association is set
+    // up.
+    let proxy = TcpListener::bind("127.0.0.1:0").await?;
+    let proxy_addr = proxy.local_addr()?;
+    tokio::spawn(async move {
+        let (mut stream, _) = proxy.accept().await?;
+        let mut greeting = [0; 4];
+        stream.read_exact(&mut greeting).await?;
+        stream.write_all(&[0x05, 0x00]).await?;
+        let mut request = [0; 10];
+        stream.read_exact(&mut request).await?;
+        stream
+            .write_all(&[0x05, 0x00, 0x00, 0x01, 127, 0, 0, 1])
+            .await?;
+        stream.write_all(&relay_port).await
+    });
+
+    let stream = TcpStream::connect(proxy_addr).await?;
+    let association = Socks5Client::new(stream, credentials("", ""))
+        .associate_udp(Ipv4Addr::UNSPECIFIED.into(), 0)
+        .await?;
+    let mut socket = Socks5UdpSocket::new(association).await?;
+
+    let mut buffer = [0; 16];
+    let result = tokio::time::timeout(Duration::from_secs(5), socket.recv_from(&mut buffer))
+        .await
+        .map_err(std::io::Error::from)?;
+    assert!(
+        matches!(
+            &result,
+            Err(ClientError::IoError(error))
+                if error.kind() == std::io::ErrorKind::ConnectionAborted
+        ),
+        "closing the connection should end the association: {result:?}"
+    );
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    io::{
+        Error as IoError,
+        ErrorKind,
+        Result as IoResult,
+    },
+    os::unix::fs::MetadataExt as _,
+    path::PathBuf,
+};
+
+use common::credentials;
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+    },
+    proto::{
+        AuthenticationMethod,
+        Reply,
+    },
+    server::{
+        IpNetwork,
+        SelectionPolicy,
+        auth::LocalPeer,
+        tokio::Socks5Listener,
+    },
+};
+use tokio::net::UnixStream;
+
+/// Binds a proxy which only accepts the processes running as the user the
+/// filter returns `true` for, given the user this test runs as.
+fn spawn_proxy(name: &str, filter: fn(u32, u32) -> bool) -> IoResult<PathBuf> {
+    spawn_configured_proxy(name, filter, |listener| listener)
+}
+
+/// Same as [spawn_proxy], with more options set on the listener.
+fn spawn_configured_proxy(
+    name: &str,
+    filter: fn(u32, u32) -> bool,
+    configure: impl FnOnce(Socks5Listener) -> Socks5Listener,
+) -> IoResult<PathBuf> {
+    let path = std::env::temp_dir().join(format!("socker-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = Socks5Listener::bind_unix_without_credentials(&path)?;
+    let owner = std::fs::metadata(&path)?.uid();
+    let listener = listener
+        .with_authenticator(LocalPeer::new(move |credentials| {
+            filter(owner, credentials.user_id)
+        }))
+        .with_selection_policy(SelectionPolicy::new([
+            AuthenticationMethod::NO_AUTHENTICATION,
+        ]));
+    let listener = configure(listener);
+    tokio::spawn(async move { listener.run().await });
+    Ok(path)
+}
+
+#[tokio::test]
+async fn local_peer_is_accepted() -> Result<(), ClientError> {
+    let proxy = spawn_proxy("accepted", |owner, peer| owner == peer)?;
+    let echo = common::spawn_echo().await?;
+
+    let stream = UnixStream::connect(&proxy).await?;
+    let mut stream = Socks5Client::from_stream(stream, credentials("", ""))
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+
+    std::fs::remove_file(proxy)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn unknown_peer_is_rejected() -> Result<(), ClientError> {
+    let proxy = spawn_proxy("rejected", |owner, peer| owner != peer)?;
+    let echo = common::spawn_echo().await?;
+
+    let stream = UnixStream::connect(&proxy).await?;
+    let result = Socks5Client::from_stream(stream, credentials("", ""))
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await;
+    assert!(result.is_err(), "connection should be refused");
+
+    std::fs::remove_file(proxy)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn trusted_networks_leave_local_peers_alone() -> Result<(), ClientError> {
+    let trusted =
+        IpNetwork::parse("10.0.0.0/8").ok_or_else(|| IoError::from(ErrorKind::InvalidInput))?;
+    let proxy = spawn_configured_proxy(
+        "trusting",
+        |owner, peer| owner == peer,
+        |listener| listener.with_trusted_network(trusted),
+    )?;
+    let echo = common::spawn_echo().await?;
+
+    let stream = UnixStream::connect(&proxy).await?;
+    let mut stream = Socks5Client::from_stream(stream, credentials("", ""))
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await?;
+    common::ping(&mut stream).await?;
+
+    std::fs::remove_file(proxy)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn request_filter_applies() -> Result<(), ClientError> {
+    let echo = common::spawn_echo().await?;
+    let proxy = spawn_configured_proxy(
+        "filtered",
+        |owner, peer| owner == peer,
+        |listener| listener.with_request_filter(move |_, request| request.port != echo.port()),
+    )?;
+
+    let stream = UnixStream::connect(&proxy).await?;
+    let result = Socks5Client::from_stream(stream, credentials("", ""))
+        .connect_to_target(echo.ip().into(), echo.port())
+        .await;
+    assert!(
+        matches!(
+            result,
+            Err(ClientError::RequestFailed(
+                Reply::CONNECTION_NOT_ALLOWED_BY_RULESET
+            ))
+        ),
+        "request should be rejected: {result:?}"
+    );
+
+    std::fs::remove_file(proxy)?;
+    Ok(())
+}
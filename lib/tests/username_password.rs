@@ -0,0 +1,87 @@
+mod common;
+
+use std::{
+    io::Result as IoResult,
+    net::SocketAddr,
+    sync,
+};
+
+use common::{
+    Credentials,
+    credentials,
+};
+use socker::socks5::{
+    client::{
+        Client,
+        ClientError,
+        tokio::Socks5Client,
+        username_password_auth_impl,
+    },
+    proto::AuthenticationMethod,
+};
+use tokio::net::TcpStream;
+
+async fn spawn_proxy() -> IoResult<SocketAddr> {
+    common::serve(common::listener().await?)
+}
+
+async fn authenticate(
+    proxy: SocketAddr,
+    creds: &Credentials,
+) -> Result<(Socks5Client, Result<(), ClientError>), ClientError> {
+    let stream = TcpStream::connect(proxy).await?;
+    let mut client = Socks5Client::new(stream, sync::Arc::clone(creds));
+
+    let choice = client
+        .perform_handshake([AuthenticationMethod::USERNAME_PASSWORD].into())
+        .await?;
+    assert_eq!(
+        choice,
+        AuthenticationMethod::USERNAME_PASSWORD,
+        "server should pick username/password"
+    );
+
+    let result = username_password_auth_impl(&mut client, creds.0.clone(), creds.1.clone()).await;
+    Ok((client, result))
+}
+
+#[tokio::test]
+async fn valid_credentials_are_accepted() -> Result<(), ClientError> {
+    let proxy = spawn_proxy().await?;
+    let echo = common::spawn_echo().await?;
+
+    let (mut client, result) = authenticate(proxy, &credentials("user", "secret")).await?;
+    assert!(
+        matches!(result, Ok(())),
+        "authentication should succeed: {result:?}"
+    );
+
+    client
+        .send_connect_request(echo.ip().into(), echo.port())
+        .await?;
+
+    let stream = client.stream();
+    futures::AsyncWriteExt::write_all(stream, b"ping").await?;
+    let mut buffer = [0; 4];
+    futures::AsyncReadExt::read_exact(stream, &mut buffer).await?;
+    assert_eq!(&buffer, b"ping", "data should be relayed");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn invalid_credentials_are_rejected() -> Result<(), ClientError> {
+    let proxy = spawn_proxy().await?;
+
+    let (mut client, result) = authenticate(proxy, &credentials("user", "wrong")).await?;
+    assert!(
+        matches!(result, Err(ClientError::AuthenticationFailed)),
+        "authentication should fail: {result:?}"
+    );
+
+    let mut buffer = [0; 1];
+    let read = futures::AsyncReadExt::read(client.stream(), &mut buffer).await?;
+    assert_eq!(read, 0, "server should close the connection");
+
+    Ok(())
+}